@@ -0,0 +1,56 @@
+#!/bin/sh
+# Benchmark of searches with and without the search index on 10 000 generated
+# contacts. Usage: scripts/bench-search.sh [number of runs]
+set -eu
+
+RUNS=${1:-20}
+COUNT=10000
+
+cargo build --release --quiet
+BIN=$(pwd)/target/release/con-rs
+
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+export XDG_DATA_HOME="$TMP/data" XDG_CONFIG_HOME="$TMP/config" XDG_CACHE_HOME="$TMP/cache"
+INDEX="$XDG_CACHE_HOME/conn-rs/search-index.json"
+
+awk -v count="$COUNT" 'BEGIN {
+    print "{\"contacts\": ["
+    for (i = 0; i < count; i++) {
+        printf "{\"id\": \"%d\", \"fullName\": \"Person%d Surname%d\", ", i, i, i % 100
+        printf "\"tels\": {\"mobile\": {\"number\": \"+420603%06d\", \"e164\": \"+420603%06d\"}}, ", i, i
+        printf "\"emails\": {\"work\": \"person%d@example.com\", \"home\": \"p%d@example.org\"}, ", i, i
+        printf "\"labels\": {\"note\": \"Generated contact number %d.\"}, ", i
+        printf "\"tags\": [\"generated\", \"batch%d\"]}%s\n", i % 10, (i < count - 1 ? "," : "")
+    }
+    print "]}"
+}' > "$TMP/contacts.json"
+"$BIN" init --from "$TMP/contacts.json" 2> /dev/null
+
+# Print average wall time of a command in milliseconds.
+measure() {
+    label=$1
+    clear_index=$2
+    shift 2
+    total=0
+    i=0
+    while [ "$i" -lt "$RUNS" ]; do
+        if [ "$clear_index" = yes ]; then
+            rm -f "$INDEX"
+        fi
+        start=$(date +%s%N)
+        "$@" > /dev/null 2>&1 || true
+        end=$(date +%s%N)
+        total=$((total + end - start))
+        i=$((i + 1))
+    done
+    echo "$label: $((total / RUNS / 1000000)) ms"
+}
+
+echo "$COUNT contacts, average of $RUNS runs"
+for query in "-q Person1234" "-n Surname42"; do
+    # shellcheck disable=SC2086
+    measure "search $query mutt (index rebuilt)" yes "$BIN" search $query mutt
+    # shellcheck disable=SC2086
+    measure "search $query mutt (index up to date)" no "$BIN" search $query mutt
+done
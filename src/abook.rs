@@ -0,0 +1,223 @@
+use crate::contact::{self, Contact};
+use crate::email;
+use crate::phone::Tel;
+use anyhow::{bail, Context, Result};
+use phonenumber::country;
+
+/// Fields of an abook entry with the keys of corresponding telephone numbers.
+const TEL_FIELDS: [(&str, &str); 4] = [
+    ("phone", "home"),
+    ("workphone", "work"),
+    ("mobile", "cell"),
+    ("fax", "fax"),
+];
+/// Fields of an abook entry which together form a postal address.
+const ADDRESS_FIELDS: [&str; 6] = ["address", "address2", "city", "state", "zip", "country"];
+
+/// Parse an abook address book (`~/.abook/addressbook`), i.e. an INI file
+/// with a section per contact, and convert its entries to contacts.
+///
+/// Entries are mapped as follows: `name` to full name, comma separated
+/// `email` to e-mail addresses, `phone`, `workphone`, `mobile` and `fax` to
+/// telephone numbers, `groups` to tags, `nick` and `notes` to labels `nick`
+/// and `note`, address fields to label `address` and all other (including
+/// custom) fields to labels of the same name. Invalid e-mail addresses are
+/// skipped with a warning.
+///
+/// # Arguments
+///
+/// * `content` - content of the address book.
+/// * `country` - country used for telephone numbers without an international
+///   calling code.
+pub fn parse(content: &str, country: Option<country::Id>) -> Result<Vec<Contact>> {
+    let mut contacts = Vec::new();
+    for (section, fields) in parse_ini(content)? {
+        // The format section describes the file, not a contact.
+        if section == "format" {
+            continue;
+        }
+        let contact = to_contact(&fields, country)
+            .with_context(|| format!("Invalid abook entry [{}].", section))?;
+        contacts.push(contact);
+    }
+    Ok(contacts)
+}
+
+fn to_contact(fields: &[(String, String)], country: Option<country::Id>) -> Result<Contact> {
+    let find = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    };
+
+    let mut contact = match find("name") {
+        Some(name) => Contact::with_full_name(name.to_owned()),
+        None => bail!("Entry without a name."),
+    };
+
+    for address in find("email").unwrap_or_default().split(',') {
+        let address = address.trim();
+        if !address.is_empty() {
+            if let Err(error) = email::validate(address) {
+                eprintln!(
+                    "Warning: skipping e-mail address of {}. {:#}",
+                    contact.name().unwrap_or_default(),
+                    error
+                );
+                continue;
+            }
+            contact::insert_unique(&mut contact.emails, "email", email::normalize(address));
+        }
+    }
+    for (field, key) in TEL_FIELDS.iter() {
+        if let Some(number) = find(field) {
+            let tel = Tel::parse(number.to_owned(), country);
+            contact::insert_unique(&mut contact.tels, key, tel);
+        }
+    }
+    for tag in find("groups").unwrap_or_default().split(',') {
+        if !tag.trim().is_empty() {
+            contact.tags.insert(tag.trim().to_owned());
+        }
+    }
+
+    let address: Vec<&str> = ADDRESS_FIELDS
+        .iter()
+        .filter_map(|field| find(field))
+        .collect();
+    if !address.is_empty() {
+        contact::insert_unique(&mut contact.labels, "address", address.join(", "));
+    }
+
+    for (key, value) in fields.iter() {
+        let key = match key.as_str() {
+            "name" | "email" | "groups" => continue,
+            key if ADDRESS_FIELDS.contains(&key) => continue,
+            key if TEL_FIELDS.iter().any(|(field, _)| *field == key) => continue,
+            "notes" => "note",
+            key => key,
+        };
+        if !value.is_empty() {
+            contact::insert_unique(&mut contact.labels, key, value.clone());
+        }
+    }
+
+    Ok(contact)
+}
+
+/// Fields of an INI section as (lowercased) key value pairs in the order of
+/// the file.
+type Fields = Vec<(String, String)>;
+
+/// Parse an INI file to named sections in the order of the file.
+fn parse_ini(content: &str) -> Result<Vec<(String, Fields)>> {
+    let mut sections: Vec<(String, Fields)> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.trim().to_owned(), Vec::new()));
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some(field) => field,
+            None => bail!("Invalid line {}: {}", index + 1, line),
+        };
+        match sections.last_mut() {
+            Some((_, fields)) => fields.push((key.trim().to_lowercase(), value.trim().to_owned())),
+            None => bail!("Line {} is not in any section.", index + 1),
+        }
+    }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An address book written by abook 0.6.
+    const ADDRESS_BOOK: &str = "# abook addressbook file
+
+[format]
+program=abook
+version=0.6.1
+
+
+[0]
+name=John Doe
+email=john@example.com,jd@Work.Example.com
+phone=+420222111000
+mobile=+420603123456
+nick=jd
+address=Main Street 1
+city=Prague
+zip=11000
+country=Czechia
+groups=friends,golf
+url=https://example.com
+custom1=foo
+
+[1]
+name=Jane Roe
+email=not-an-address,jane@example.com
+notes=Met at a conference.
+";
+
+    fn label<'a>(contact: &'a Contact, key: &str) -> Option<&'a str> {
+        contact.labels.get(key).map(String::as_str)
+    }
+
+    #[test]
+    fn address_book() {
+        let contacts = parse(ADDRESS_BOOK, None).unwrap();
+        assert_eq!(contacts.len(), 2);
+
+        let john = &contacts[0];
+        assert_eq!(john.full_name(), Some("John Doe"));
+        let emails: Vec<(&str, &str)> = john
+            .emails
+            .iter()
+            .map(|(key, address)| (key.as_str(), address.as_str()))
+            .collect();
+        assert_eq!(
+            emails,
+            [
+                ("email", "john@example.com"),
+                ("email 2", "jd@work.example.com")
+            ]
+        );
+        let tels: Vec<(&str, &str)> = john
+            .tels
+            .iter()
+            .map(|(key, tel)| (key.as_str(), tel.number()))
+            .collect();
+        assert_eq!(tels, [("home", "+420222111000"), ("cell", "+420603123456")]);
+        let tags: Vec<&str> = john.tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, ["friends", "golf"]);
+        assert_eq!(label(john, "nick"), Some("jd"));
+        assert_eq!(
+            label(john, "address"),
+            Some("Main Street 1, Prague, 11000, Czechia")
+        );
+        assert_eq!(label(john, "url"), Some("https://example.com"));
+        assert_eq!(label(john, "custom1"), Some("foo"));
+
+        let jane = &contacts[1];
+        let addresses: Vec<&str> = jane.emails.values().map(String::as_str).collect();
+        assert_eq!(addresses, ["jane@example.com"]);
+        assert_eq!(label(jane, "note"), Some("Met at a conference."));
+    }
+
+    #[test]
+    fn invalid_address_books() {
+        assert!(parse("name=John Doe\n", None).is_err());
+        assert!(parse("[0]\nname John Doe\n", None).is_err());
+        assert!(parse("[0]\nemail=jd@example.com\n", None).is_err());
+    }
+}
@@ -5,4 +5,11 @@ pub trait MatchAction {
     /// Process contacts and return true if the contacts have been modified
     /// along the way, in which case they will be saved.
     fn process(&self, contacts: Vec<&mut Contact>) -> Result<bool>;
+
+    /// Returns true if the action never modifies contacts. Such actions can
+    /// be given contacts loaded only partially, see
+    /// `ContactStore::load_matching()`.
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
@@ -1,9 +1,15 @@
-use crate::contact::{Contact, Contacts};
+use crate::config::Config;
+use crate::contact::Contact;
+use crate::email;
+use crate::phone::Tel;
+use crate::storage::ContactStore;
 use anyhow::{bail, Result};
 use dialoguer::Input;
-use std::collections::HashMap;
+use indexmap::IndexMap;
+
+pub fn add_contact(store: &dyn ContactStore) -> Result<()> {
+    let country = Config::load()?.default_country()?;
 
-pub fn add_contact() -> Result<()> {
     let (entity_name, full_name) = loop {
         let full_name = prompt("Full name [skip if not a person]")?;
 
@@ -21,32 +27,74 @@ pub fn add_contact() -> Result<()> {
         break (entity_name, full_name);
     };
 
-    let mut contact = if full_name.is_some() {
-        Contact::with_full_name(full_name.unwrap())
-    } else {
-        Contact::with_entity_name(entity_name.unwrap())
+    let mut contact = match full_name {
+        Some(full_name) => {
+            let mut contact = Contact::with_full_name(full_name);
+            contact.set_organization(prompt("Organization [optional]")?)?;
+            contact.set_title(prompt("Title [optional]")?)?;
+            contact
+        }
+        None => Contact::with_entity_name(entity_name.unwrap()),
     };
 
-    contact.emails = prompt_map("E-mail address")?;
-    contact.tels = prompt_map("Telephone number")?;
-    contact.labels = prompt_map("Label")?;
+    contact.emails = prompt_map("E-mail address", email::parse)?;
+    contact.tels = prompt_map("Telephone number", |value| {
+        let tel = Tel::parse(value, country);
+        tel.warn_if_invalid(country);
+        Ok(tel)
+    })?;
+    contact.labels = prompt_map("Label", Ok)?;
+    if let Some(tags) = prompt("Tags [comma separated, optional]")? {
+        contact.tags = tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect();
+    }
 
-    let mut contacts = Contacts::load_from_home()?;
-    contacts.add(contact);
-    contacts.save_to_home()?;
+    add(store, contact)?;
 
     eprintln!("Contact successfully added.");
 
     Ok(())
 }
 
-fn prompt_map(name: &str) -> Result<HashMap<String, String>> {
+/// Add a contact to stored contacts. A warning is printed for e-mail
+/// addresses already used by other contacts.
+fn add(store: &dyn ContactStore, contact: Contact) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+    for address in contact.emails.values() {
+        if let Some(other) = contacts.find_by_email(address) {
+            eprintln!(
+                "Warning: e-mail address {} is already used by contact {}.",
+                address,
+                other.name().unwrap_or("<unnamed>")
+            );
+        }
+    }
+
+    contacts.upsert(contact);
+    store.save(&mut contacts)
+}
+
+/// Repeatedly prompt for map items until an empty value is entered.
+///
+/// # Arguments
+///
+/// * `name` - human readable name of the items.
+/// * `convert` - function which validates / converts the entered value. The
+///   user is asked again if it returns an error.
+fn prompt_map<V, F>(name: &str, convert: F) -> Result<IndexMap<String, V>>
+where
+    F: Fn(String) -> Result<V>,
+{
     eprintln!(
         "\nYou will be repeatedly asked for {} until you enter empty value.\n",
         name,
     );
 
-    let mut map: HashMap<String, String> = HashMap::new();
+    let mut map: IndexMap<String, V> = IndexMap::new();
 
     loop {
         let key = match prompt(&format!("{} name", name))? {
@@ -54,7 +102,7 @@ fn prompt_map(name: &str) -> Result<HashMap<String, String>> {
             None => break,
         };
 
-        let value = prompt(&format!("{}", name))?;
+        let value = prompt(name)?;
         if value.is_none() {
             break;
         }
@@ -64,7 +112,12 @@ fn prompt_map(name: &str) -> Result<HashMap<String, String>> {
             bail!("The map already contains key {}", key);
         }
 
-        map.insert(key, value);
+        match convert(value) {
+            Ok(value) => {
+                map.insert(key, value);
+            }
+            Err(error) => eprintln!("{}", error),
+        }
     }
 
     Ok(map)
@@ -80,5 +133,43 @@ fn prompt(name: &str) -> Result<Option<String>> {
         return Ok(None);
     }
 
-    return Ok(Some(result));
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    #[test]
+    fn add_to_stored_contacts() {
+        let store = MemoryStore::with_contacts(json!({"contacts": []}));
+        let mut contact = Contact::with_full_name(String::from("John Doe"));
+        contact
+            .emails
+            .insert(String::from("work"), String::from("jd@example.com"));
+        add(&store, contact).unwrap();
+
+        let mut other = Contact::with_full_name(String::from("Jane Doe"));
+        other
+            .emails
+            .insert(String::from("work"), String::from("jd@example.com"));
+        add(&store, other).unwrap();
+
+        let contacts = store.load_valid().unwrap();
+        let names: Vec<&str> = contacts
+            .contacts()
+            .iter()
+            .filter_map(Contact::full_name)
+            .collect();
+        assert_eq!(names, ["John Doe", "Jane Doe"]);
+    }
+
+    #[test]
+    fn add_to_uninitialized_store() {
+        let store = MemoryStore::default();
+        let contact = Contact::with_full_name(String::from("John Doe"));
+        assert!(add(&store, contact).is_err());
+    }
 }
@@ -0,0 +1,127 @@
+use crate::contact::Contacts;
+use crate::storage::StorageKind;
+use anyhow::{bail, Context, Result};
+use phonenumber::country;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind};
+use std::path::PathBuf;
+
+/// User configuration. All configuration options are optional and a default
+/// configuration is used if the configuration file does not exist.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    default_country: Option<String>,
+    storage: Option<String>,
+    vdir: Option<String>,
+    encryption_key_file: Option<String>,
+}
+
+impl Config {
+    /// Load configuration from the standard configuration file, see
+    /// `get_config_file()`.
+    pub fn load() -> Result<Self> {
+        let config_path = Self::get_config_file()?;
+        let file = match File::open(config_path.as_path()) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("Could not open config file: {}", config_path.display())
+                })
+            }
+        };
+
+        let reader = BufReader::new(file);
+        let config = serde_json::from_reader(reader)
+            .with_context(|| format!("Error while loading config: {}", config_path.display()))?;
+        Ok(config)
+    }
+
+    /// Country used for telephone numbers given without an international
+    /// calling code. It is configured as ISO 3166-1 alpha-2 code (e.g. `CZ`).
+    pub fn default_country(&self) -> Result<Option<country::Id>> {
+        match &self.default_country {
+            Some(code) => match code.to_uppercase().parse() {
+                Ok(id) => Ok(Some(id)),
+                Err(_) => bail!("Invalid default country in config: {}", code),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Layout of contacts in the directory with contacts, either `file`
+    /// (default), `directory`, `vdir` or `sqlite`.
+    pub fn storage(&self) -> Result<StorageKind> {
+        match &self.storage {
+            Some(kind) => kind
+                .parse()
+                .with_context(|| format!("Invalid storage in config: {}", kind)),
+            None => Ok(StorageKind::File),
+        }
+    }
+
+    /// Directory with vCard files used by `vdir` storage. Defaults to `vdir`
+    /// in the directory with contacts.
+    pub fn vdir(&self) -> Result<PathBuf> {
+        match &self.vdir {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => Ok(Contacts::get_contacts_dir()?.join("vdir")),
+        }
+    }
+
+    /// age identity file used to encrypt and decrypt contacts, see
+    /// `encryption`. Contacts are encrypted with a passphrase if no key file
+    /// is configured.
+    pub fn encryption_key_file(&self) -> Option<PathBuf> {
+        self.encryption_key_file.as_ref().map(PathBuf::from)
+    }
+
+    /// Set storage in the configuration file, see `storage()`. Other options
+    /// in the file are kept.
+    pub fn set_storage(storage: &str) -> Result<()> {
+        let config_path = Self::get_config_file()?;
+        let mut config: Map<String, Value> = match fs::read_to_string(&config_path) {
+            Ok(content) => serde_json::from_str(&content).with_context(|| {
+                format!("Error while loading config: {}", config_path.display())
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Map::new(),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("Could not open config file: {}", config_path.display())
+                })
+            }
+        };
+        config.insert(String::from("storage"), Value::from(storage));
+
+        if let Some(config_dir) = config_path.parent() {
+            fs::create_dir_all(config_dir).context("Could not create config directory.")?;
+        }
+        let content = serde_json::to_string_pretty(&config)? + "\n";
+        fs::write(&config_path, content)
+            .with_context(|| format!("Could not write config file: {}", config_path.display()))
+    }
+
+    /// Get path to standard config file.
+    fn get_config_file() -> Result<PathBuf> {
+        let mut config_path = match env::var("XDG_CONFIG_HOME") {
+            Ok(val) => PathBuf::from(val),
+            Err(_) => {
+                let home = match env::var("HOME") {
+                    Ok(home) => home,
+                    Err(_) => bail!("Neither XDG_CONFIG_HOME nor HOME environment variable set."),
+                };
+                let mut path = PathBuf::from(home);
+                path.push(".config");
+                path
+            }
+        };
+
+        config_path.push("conn-rs");
+        config_path.push("config.json");
+        Ok(config_path)
+    }
+}
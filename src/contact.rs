@@ -1,87 +1,432 @@
-use anyhow::{bail, Context, Result};
+use crate::email;
+use crate::encryption;
+use crate::phone::Tel;
+use crate::storage;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fmt;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::fs;
 use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Name of the contacts file in the directory with contacts.
+pub const CONTACTS_FILE: &str = "contacts.json";
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Contacts {
     contacts: Vec<Contact>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<Group>,
 }
 
+/// A named set of contacts, e.g. a distribution list.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub name: String,
+    /// IDs of member contacts.
+    #[serde(default)]
+    pub contacts: Vec<String>,
+    /// Names of nested groups. Members of nested groups are members of this
+    /// group as well.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Contact {
+    /// Unique and stable ID of the contact. It is generated for contacts
+    /// stored without an ID.
+    #[serde(default = "generate_id")]
+    id: String,
     full_name: Option<String>,
     entity_name: Option<String>,
-    pub tels: HashMap<String, String>,
-    pub emails: HashMap<String, String>,
-    pub labels: HashMap<String, String>,
+    /// Organization (e.g. employer) of a person.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    organization: Option<String>,
+    /// Title (e.g. job title) of a person.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// Telephone numbers ordered by preference, see `set_preferred()`.
+    pub tels: IndexMap<String, Tel>,
+    /// E-mail addresses ordered by preference, see `set_preferred()`.
+    pub emails: IndexMap<String, String>,
+    pub labels: IndexMap<String, String>,
+    /// Arbitrary tags, e.g. `project=alpha`. Unlike labels, a contact can
+    /// have multiple tags with the same prefix.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    relations: Vec<Relation>,
+    /// Time of creation of the contact. This is missing for contacts created
+    /// before timestamps were introduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+    /// Time of the last modification of the contact. It is updated whenever
+    /// a changed contact is saved, see `Contacts::save_to_path()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    removal: Option<Removal>,
+}
+
+/// A directed relation to another contact.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Relation {
+    /// Type of the relation, e.g. `spouse` or `works-at`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// ID of the related contact.
+    pub contact: String,
+    /// Name of the related contact. This is resolved when contacts are
+    /// loaded.
+    #[serde(skip)]
+    name: Option<String>,
+}
+
+/// Reason for removal of a contact, see `Contacts::remove_marked()`.
+#[derive(Clone)]
+enum Removal {
+    Deleted,
+    /// The contact has been merged into a contact with the given ID.
+    MergedInto(String),
+}
+
+/// A contact loaded from the contacts file which does not satisfy invariants
+/// of `Contact`.
+pub struct ContactError {
+    /// Index of the contact in the contacts file.
+    pub index: usize,
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ContactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "contact #{} ({}): {}",
+            self.index, self.field, self.message
+        )
+    }
 }
 
 impl Contacts {
     pub fn new() -> Self {
         Self {
             contacts: Vec::new(),
+            groups: Vec::new(),
         }
     }
 
     /// Load contacts data from an arbitrary path. The path must point to a
-    /// valid JSON file with contact data.
+    /// valid JSON file with contact data. All contacts must satisfy contact
+    /// invariants, see `load_from_path_lenient()`.
     pub fn load_from_path<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let file = File::open(path)
+        let (contacts, errors) = Self::load_from_path_lenient(path)?;
+        check_errors(&errors)?;
+        Ok(contacts)
+    }
+
+    /// Load contacts data from an arbitrary path. The path must point to a
+    /// valid JSON file with contact data, possibly encrypted (see
+    /// `encryption`). Contacts which do not satisfy contact invariants (e.g. a
+    /// contact with both full name and entity name) are loaded as well and
+    /// reported in the returned vector.
+    pub fn load_from_path_lenient<P>(path: P) -> Result<(Self, Vec<ContactError>)>
+    where
+        P: AsRef<Path>,
+    {
+        let content = fs::read_to_string(path)
             .context("Could not load contacts. Make sure contacts are initialized.")?;
-        let reader = BufReader::new(file);
-        let contact = serde_json::from_reader(reader).context("Error while loading contacts.")?;
-        Ok(contact)
+        let content = encryption::decrypt_if_encrypted(content)?;
+        let mut deserializer = serde_json::Deserializer::from_str(&content);
+        let contacts: Self =
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+                anyhow!(
+                    "Error while loading contacts at {}: {}",
+                    error.path(),
+                    error.inner()
+                )
+            })?;
+        Ok(Self::from_parts(contacts.contacts, contacts.groups))
+    }
+
+    /// Create contacts from loaded contacts and groups. Contacts which do not
+    /// satisfy contact invariants are reported in the returned vector, see
+    /// `load_from_path_lenient()`.
+    pub fn from_parts(contacts: Vec<Contact>, groups: Vec<Group>) -> (Self, Vec<ContactError>) {
+        let mut contacts = Self { contacts, groups };
+        contacts.resolve_relations();
+        let errors = contacts.validate();
+        (contacts, errors)
     }
 
-    /// Load contacts data from a standard path. See `load_from_path()`.
+    /// Load contacts from the configured storage, see `storage::open()`. All
+    /// contacts must satisfy contact invariants.
     pub fn load_from_home() -> Result<Self> {
-        let data_path = Self::get_contacts_file(false)?;
-        Self::load_from_path(data_path.as_path())
+        storage::open()?.load_valid()
+    }
+
+    /// Check invariants of all contacts.
+    fn validate(&self) -> Vec<ContactError> {
+        let mut ids = HashSet::new();
+
+        self.contacts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, contact)| {
+                let error = if ids.insert(contact.id.as_str()) {
+                    contact.validate()
+                } else {
+                    Some(("id", "duplicate contact ID"))
+                };
+
+                error.map(|(field, message)| ContactError {
+                    index,
+                    field,
+                    message,
+                })
+            })
+            .collect()
+    }
+
+    /// Resolve names of related contacts, see `Relation`.
+    fn resolve_relations(&mut self) {
+        let names: HashMap<String, Option<String>> = self
+            .contacts
+            .iter()
+            .map(|contact| (contact.id.clone(), contact.name().map(String::from)))
+            .collect();
+
+        for contact in self.contacts.iter_mut() {
+            for relation in contact.relations.iter_mut() {
+                relation.name = names.get(&relation.contact).cloned().flatten();
+            }
+        }
+    }
+
+    /// Remove all contacts which have been deleted or merged into another
+    /// contact. Relations to removed contacts are either redirected to the
+    /// contact they were merged into or removed.
+    pub fn remove_marked(&mut self) {
+        let mut removed: HashMap<String, Option<String>> = HashMap::new();
+        for contact in self.contacts.iter() {
+            match &contact.removal {
+                Some(Removal::Deleted) => {
+                    removed.insert(contact.id.clone(), None);
+                }
+                Some(Removal::MergedInto(id)) => {
+                    removed.insert(contact.id.clone(), Some(id.clone()));
+                }
+                None => (),
+            }
+        }
+
+        if removed.is_empty() {
+            return;
+        }
+
+        self.contacts.retain(|contact| contact.removal.is_none());
+
+        for contact in self.contacts.iter_mut() {
+            let relations = std::mem::take(&mut contact.relations);
+            for mut relation in relations {
+                match removed.get(&relation.contact) {
+                    Some(Some(id)) => relation.contact = id.clone(),
+                    Some(None) => continue,
+                    None => (),
+                }
+                if relation.contact != contact.id && !contact.has_relation(&relation) {
+                    contact.relations.push(relation);
+                }
+            }
+        }
+
+        for group in self.groups.iter_mut() {
+            let members = std::mem::take(&mut group.contacts);
+            for id in members {
+                let id = match removed.get(&id) {
+                    Some(Some(merged_into)) => merged_into.clone(),
+                    Some(None) => continue,
+                    None => id,
+                };
+                if !group.contacts.contains(&id) {
+                    group.contacts.push(id);
+                }
+            }
+        }
+
+        self.resolve_relations();
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        self.groups.as_ref()
+    }
+
+    /// Find a group by its name.
+    pub fn group_mut(&mut self, name: &str) -> Result<&mut Group> {
+        match self.groups.iter_mut().find(|group| group.name == name) {
+            Some(group) => Ok(group),
+            None => bail!("Group {} does not exist.", name),
+        }
+    }
+
+    /// Create a new empty group.
+    pub fn create_group(&mut self, name: String) -> Result<()> {
+        if self.groups.iter().any(|group| group.name == name) {
+            bail!("Group {} already exists.", name);
+        }
+        self.groups.push(Group {
+            name,
+            contacts: Vec::new(),
+            groups: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Return IDs of all members of a group including members of nested
+    /// groups.
+    pub fn group_members(&self, name: &str) -> Result<HashSet<String>> {
+        if !self.groups.iter().any(|group| group.name == name) {
+            bail!("Group {} does not exist.", name);
+        }
+
+        let mut members = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![name];
+
+        while let Some(name) = stack.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            if let Some(group) = self.groups.iter().find(|group| group.name == name) {
+                members.extend(group.contacts.iter().cloned());
+                stack.extend(group.groups.iter().map(String::as_str));
+            }
+        }
+
+        Ok(members)
     }
 
     /// Save contact data to an arbitrary path. If the file already exists it
-    /// will be truncated.
-    pub fn save_to_path<P>(&self, path: P) -> Result<()>
+    /// will be truncated. An encrypted file stays encrypted.
+    ///
+    /// Contacts and groups are sorted before saving so that the file content
+    /// does not depend on order of modifications. Modification time is
+    /// updated for all contacts which differ from the contacts stored in the
+    /// file.
+    pub fn save_to_path<P>(&mut self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let file = File::create(path).context("Error while saving contacts.")?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &self).context("Error while saving contacts.")?;
-        Ok(())
+        let previous = fs::read_to_string(path.as_ref()).ok();
+        let encrypted = previous.as_deref().is_some_and(encryption::is_encrypted);
+        if let Some(previous) = previous {
+            self.touch_modified(&encryption::decrypt_if_encrypted(previous)?);
+        }
+        self.sort();
+
+        let mut content =
+            serde_json::to_string_pretty(&self).context("Error while saving contacts.")? + "\n";
+        if encrypted {
+            content = encryption::encrypt(&content)?;
+        }
+        fs::write(path, content).context("Error while saving contacts.")
+    }
+
+    /// Update modification time of all contacts which differ from their
+    /// previous version stored in a file. Nothing is updated if the previous
+    /// content of the file cannot be parsed.
+    fn touch_modified(&mut self, previous: &str) {
+        let previous: serde_json::Value = match serde_json::from_str(previous) {
+            Ok(previous) => previous,
+            Err(_) => return,
+        };
+
+        let previous: HashMap<&str, &serde_json::Value> = match previous["contacts"].as_array() {
+            Some(contacts) => contacts
+                .iter()
+                .filter_map(|contact| contact["id"].as_str().map(|id| (id, contact)))
+                .collect(),
+            None => return,
+        };
+
+        for contact in self.contacts.iter_mut() {
+            if let Some(previous) = previous.get(contact.id.as_str()) {
+                contact.touch_if_changed(previous);
+            }
+        }
+    }
+
+    /// Sort contacts by name (case insensitive) and ID and groups by name.
+    pub fn sort(&mut self) {
+        self.contacts.sort_by_cached_key(|contact| {
+            let name = contact.name().map(str::to_lowercase);
+            (name, contact.id.clone())
+        });
+        self.groups.sort_by(|a, b| a.name.cmp(&b.name));
     }
 
-    /// Save contacts to standard path. Pre-existing file is truncated.
-    pub fn save_to_home(&self) -> Result<()> {
-        let data_path = Self::get_contacts_file(true)?;
-        self.save_to_path(data_path)
+    pub fn contacts(&self) -> &[Contact] {
+        self.contacts.as_ref()
     }
 
     pub fn contacts_mut(&mut self) -> &mut [Contact] {
         self.contacts.as_mut()
     }
 
+    /// Find a contact with a given e-mail address. Addresses are compared in
+    /// their normalized form.
+    pub fn find_by_email(&self, address: &str) -> Option<&Contact> {
+        let address = email::normalize(address);
+        self.contacts.iter().find(|contact| {
+            contact
+                .emails
+                .values()
+                .any(|other| email::normalize(other) == address)
+        })
+    }
+
     pub fn add(&mut self, contact: Contact) {
         self.contacts.push(contact);
     }
 
+    /// Find a contact by its ID.
+    pub fn get(&self, id: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|contact| contact.id == id)
+    }
+
+    /// Replace a contact with the same ID or add the contact if there is no
+    /// such contact.
+    pub fn upsert(&mut self, contact: Contact) {
+        match self
+            .contacts
+            .iter_mut()
+            .find(|other| other.id == contact.id)
+        {
+            Some(other) => *other = contact,
+            None => self.contacts.push(contact),
+        }
+    }
+
     /// Get standard contacts JSON file path and create.
     ///
     /// # Arguments
     ///
     /// * `create_dir` - create standard contacts directory if it does not
     ///   exist.
-    fn get_contacts_file(create_dir: bool) -> Result<PathBuf> {
+    pub fn get_contacts_file(create_dir: bool) -> Result<PathBuf> {
         let mut data_path = Self::get_contacts_dir()?;
 
         if create_dir {
@@ -93,7 +438,7 @@ impl Contacts {
             })?;
         }
 
-        data_path.push("contacts.json");
+        data_path.push(CONTACTS_FILE);
         Ok(data_path)
     }
 
@@ -121,24 +466,46 @@ impl Contacts {
 impl Contact {
     pub fn with_full_name(full_name: String) -> Self {
         Self {
+            id: generate_id(),
             full_name: Some(full_name),
             entity_name: None,
-            tels: HashMap::new(),
-            emails: HashMap::new(),
-            labels: HashMap::new(),
+            organization: None,
+            title: None,
+            tels: IndexMap::new(),
+            emails: IndexMap::new(),
+            labels: IndexMap::new(),
+            tags: BTreeSet::new(),
+            relations: Vec::new(),
+            created: Some(Utc::now()),
+            modified: Some(Utc::now()),
+            removal: None,
         }
     }
 
     pub fn with_entity_name(entity_name: String) -> Self {
         Self {
+            id: generate_id(),
             full_name: None,
             entity_name: Some(entity_name),
-            tels: HashMap::new(),
-            emails: HashMap::new(),
-            labels: HashMap::new(),
+            organization: None,
+            title: None,
+            tels: IndexMap::new(),
+            emails: IndexMap::new(),
+            labels: IndexMap::new(),
+            tags: BTreeSet::new(),
+            relations: Vec::new(),
+            created: Some(Utc::now()),
+            modified: Some(Utc::now()),
+            removal: None,
         }
     }
 
+    /// Set ID of an imported contact. The ID must be unique among all
+    /// contacts.
+    pub fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
     pub fn set_full_name(&mut self, full_name: String) -> Result<()> {
         if self.entity_name.is_some() {
             bail!("Full name and entity name cannot be set at the same time.");
@@ -155,13 +522,234 @@ impl Contact {
         Ok(())
     }
 
+    /// Set organization of a person. Pass None to remove the organization.
+    pub fn set_organization(&mut self, organization: Option<String>) -> Result<()> {
+        if organization.is_some() && self.entity_name.is_some() {
+            bail!("Only persons can belong to an organization.");
+        }
+        self.organization = organization;
+        Ok(())
+    }
+
+    /// Set title of a person. Pass None to remove the title.
+    pub fn set_title(&mut self, title: Option<String>) -> Result<()> {
+        if title.is_some() && self.entity_name.is_some() {
+            bail!("Only persons can have a title.");
+        }
+        self.title = title;
+        Ok(())
+    }
+
+    /// Convert a person contact to a non-person entity. Full name of the
+    /// person becomes name of the entity.
+    pub fn convert_to_entity(&mut self) -> Result<()> {
+        if self.full_name.is_none() {
+            bail!("Only person contacts can be converted to an entity.");
+        }
+        if self.organization.is_some() || self.title.is_some() {
+            bail!("Organization and title must be removed before conversion to an entity.");
+        }
+        self.entity_name = self.full_name.take();
+        Ok(())
+    }
+
+    /// Convert a non-person entity contact to a person. Name of the entity
+    /// becomes full name of the person.
+    pub fn convert_to_person(&mut self) -> Result<()> {
+        if self.entity_name.is_none() {
+            bail!("Only entity contacts can be converted to a person.");
+        }
+        self.full_name = self.entity_name.take();
+        Ok(())
+    }
+
+    /// Add a relation to another contact. Relations of the same type to the
+    /// same contact are added only once.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - type of the relation.
+    /// * `other_id` - ID of the related contact.
+    /// * `other_name` - name of the related contact.
+    pub fn add_relation(
+        &mut self,
+        kind: String,
+        other_id: String,
+        other_name: Option<String>,
+    ) -> Result<()> {
+        if other_id == self.id {
+            bail!("A contact cannot be related to itself.");
+        }
+
+        let relation = Relation {
+            kind,
+            contact: other_id,
+            name: other_name,
+        };
+        if !self.has_relation(&relation) {
+            self.relations.push(relation);
+        }
+        Ok(())
+    }
+
+    /// Remove relations of a given type to a given contact. Return true if
+    /// any relation has been removed.
+    pub fn remove_relation(&mut self, kind: &str, other_id: &str) -> bool {
+        let len = self.relations.len();
+        self.relations
+            .retain(|relation| relation.kind != kind || relation.contact != other_id);
+        len != self.relations.len()
+    }
+
+    fn has_relation(&self, relation: &Relation) -> bool {
+        self.relations
+            .iter()
+            .any(|other| other.kind == relation.kind && other.contact == relation.contact)
+    }
+
+    /// Mark the contact as deleted. The contact is removed by
+    /// `Contacts::remove_marked()`.
+    pub fn delete(&mut self) {
+        self.removal = Some(Removal::Deleted);
+    }
+
+    /// Merge other contact into this contact. Items of the other contact
+    /// maps are added to this contact, items with an already used key and a
+    /// different value are added under a new key. The other contact is
+    /// marked to be removed and relations to it are redirected to this
+    /// contact by `Contacts::remove_marked()`.
+    pub fn merge(&mut self, other: &mut Contact) {
+        merge_map(&mut self.tels, &other.tels, |a, b| a.matches(b));
+        merge_map(&mut self.emails, &other.emails, |a, b| {
+            email::normalize(a) == email::normalize(b)
+        });
+        merge_map(&mut self.labels, &other.labels, |a, b| a == b);
+
+        if self.full_name.is_some() {
+            if self.organization.is_none() {
+                self.organization = other.organization.clone();
+            }
+            if self.title.is_none() {
+                self.title = other.title.clone();
+            }
+        }
+
+        self.tags.extend(other.tags.iter().cloned());
+
+        self.created = match (self.created, other.created) {
+            (Some(created), Some(other_created)) => Some(created.min(other_created)),
+            (created, other_created) => created.or(other_created),
+        };
+
+        for relation in other.relations.iter() {
+            if relation.contact != self.id && !self.has_relation(relation) {
+                self.relations.push(relation.clone());
+            }
+        }
+
+        other.removal = Some(Removal::MergedInto(self.id.clone()));
+    }
+
+    /// Update modification time of the contact if it differs from its
+    /// previous serialized version. Returns true if the contact has changed.
+    pub fn touch_if_changed(&mut self, previous: &serde_json::Value) -> bool {
+        let changed = self.differs_from(previous);
+        if changed {
+            self.modified = Some(Utc::now());
+        }
+        changed
+    }
+
+    /// Returns true if the contact differs from its serialized version. The
+    /// previous version is parsed and serialized again, so that differences
+    /// in representation only (e.g. a telephone number stored as a string or
+    /// a missing `entityName`) are not changes. The modification time is not
+    /// compared.
+    fn differs_from(&self, previous: &serde_json::Value) -> bool {
+        let previous = serde_json::from_value::<Contact>(previous.clone())
+            .and_then(|previous| serde_json::to_value(&previous));
+        let (mut current, mut previous) = match (serde_json::to_value(self), previous) {
+            (Ok(current), Ok(previous)) => (current, previous),
+            _ => return true,
+        };
+
+        for value in [&mut current, &mut previous] {
+            if let Some(object) = value.as_object_mut() {
+                object.remove("modified");
+            }
+        }
+
+        current != previous
+    }
+
+    pub fn set_created(&mut self, created: Option<DateTime<Utc>>) {
+        self.created = created;
+    }
+
+    pub fn set_modified(&mut self, modified: Option<DateTime<Utc>>) {
+        self.modified = modified;
+    }
+
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        self.created
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn relations(&self) -> &[Relation] {
+        self.relations.as_ref()
+    }
+
+    /// The preferred e-mail address.
+    pub fn preferred_email(&self) -> Option<&str> {
+        self.emails.values().next().map(String::as_str)
+    }
+
     pub fn full_name(&self) -> Option<&str> {
         self.full_name.as_ref().map(String::as_ref)
     }
 
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_ref().map(String::as_ref)
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(String::as_ref)
+    }
+
     pub fn entity_name(&self) -> Option<&str> {
         self.entity_name.as_ref().map(String::as_ref)
     }
+
+    /// Check contact invariants and return the invalid field together with
+    /// an error message if they are not satisfied.
+    pub fn validate(&self) -> Option<(&'static str, &'static str)> {
+        match (&self.full_name, &self.entity_name) {
+            (Some(_), Some(_)) => Some((
+                "entityName",
+                "full name and entity name cannot be set at the same time",
+            )),
+            (None, None) => Some(("fullName", "either full name or entity name must be set")),
+            (None, Some(_)) if self.organization.is_some() => {
+                Some(("organization", "only persons can belong to an organization"))
+            }
+            (None, Some(_)) if self.title.is_some() => {
+                Some(("title", "only persons can have a title"))
+            }
+            _ => None,
+        }
+    }
+
+    /// Full name of a person or name of a non-person entity.
+    pub fn name(&self) -> Option<&str> {
+        self.full_name().or_else(|| self.entity_name())
+    }
 }
 
 impl fmt::Display for Contact {
@@ -172,19 +760,21 @@ impl fmt::Display for Contact {
         if let Some(entity_name) = &self.entity_name {
             writeln!(f, "Entity name: {}", entity_name)?;
         }
+        if let Some(organization) = &self.organization {
+            writeln!(f, "Organization: {}", organization)?;
+        }
+        if let Some(title) = &self.title {
+            writeln!(f, "Title: {}", title)?;
+        }
 
         if !self.tels.is_empty() {
             writeln!(f, "Telephone numbers:")?;
-            for (key, value) in &self.tels {
-                writeln!(f, "  {}: {}", key, value)?;
-            }
+            write_preferred_map(f, &self.tels)?;
         }
 
         if !self.emails.is_empty() {
             writeln!(f, "Emails:")?;
-            for (key, value) in &self.emails {
-                writeln!(f, "  {}: {}", key, value)?;
-            }
+            write_preferred_map(f, &self.emails)?;
         }
 
         if !self.labels.is_empty() {
@@ -194,6 +784,382 @@ impl fmt::Display for Contact {
             }
         }
 
+        if !self.tags.is_empty() {
+            let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            writeln!(f, "Tags: {}", tags.join(", "))?;
+        }
+
+        if !self.relations.is_empty() {
+            writeln!(f, "Relations:")?;
+            for relation in &self.relations {
+                match &relation.name {
+                    Some(name) => writeln!(f, "  {}: {}", relation.kind, name)?,
+                    None => writeln!(
+                        f,
+                        "  {}: <unknown contact {}>",
+                        relation.kind, relation.contact
+                    )?,
+                }
+            }
+        }
+
+        if let Some(created) = self.created {
+            writeln!(f, "Created: {}", format_time(created))?;
+        }
+        if let Some(modified) = self.modified {
+            writeln!(f, "Modified: {}", format_time(modified))?;
+        }
+
         Ok(())
     }
 }
+
+/// Write map items, the first item is marked as preferred if there is more
+/// than one item.
+fn write_preferred_map<V>(f: &mut fmt::Formatter<'_>, map: &IndexMap<String, V>) -> fmt::Result
+where
+    V: fmt::Display,
+{
+    for (index, (key, value)) in map.iter().enumerate() {
+        if index == 0 && map.len() > 1 {
+            writeln!(f, "  {}: {} (preferred)", key, value)?;
+        } else {
+            writeln!(f, "  {}: {}", key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Make an item of a map ordered by preference the preferred one by moving
+/// it to the front of the map. Returns false if there is no item with the
+/// key.
+pub fn set_preferred<V>(map: &mut IndexMap<String, V>, key: &str) -> bool {
+    match map.get_index_of(key) {
+        Some(index) => {
+            map.move_index(index, 0);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Format a timestamp in local time for humans.
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Fail if any contacts do not satisfy contact invariants.
+pub fn check_errors(errors: &[ContactError]) -> Result<()> {
+    match errors.len() {
+        0 => Ok(()),
+        1 => bail!("Invalid contact: {}", errors[0]),
+        n => bail!(
+            "Invalid contact: {} (and {} more invalid contacts)",
+            errors[0],
+            n - 1
+        ),
+    }
+}
+
+fn generate_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Add items of `source` to `target`. Items whose key is already used in
+/// `target` with a different value are added under a new key.
+fn merge_map<V, F>(target: &mut IndexMap<String, V>, source: &IndexMap<String, V>, same: F)
+where
+    V: Clone,
+    F: Fn(&V, &V) -> bool,
+{
+    for (key, value) in source.iter() {
+        if target.values().any(|other| same(value, other)) {
+            continue;
+        }
+
+        insert_unique(target, key, value.clone());
+    }
+}
+
+/// Insert an item to a map. If the key is already used, a number is appended
+/// to it (e.g. `work 2`). Returns the key used.
+pub fn insert_unique<V>(map: &mut IndexMap<String, V>, key: &str, value: V) -> String {
+    let mut new_key = key.to_owned();
+    let mut counter = 1;
+    while map.contains_key(&new_key) {
+        counter += 1;
+        new_key = format!("{} {}", key, counter);
+    }
+    map.insert(new_key.clone(), value);
+    new_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person(id: &str, full_name: &str) -> Contact {
+        let mut contact = Contact::with_full_name(full_name.to_owned());
+        contact.set_id(id.to_owned());
+        contact
+    }
+
+    fn related(contact: &Contact) -> Vec<(&str, &str, Option<&str>)> {
+        contact
+            .relations()
+            .iter()
+            .map(|relation| {
+                (
+                    relation.kind.as_str(),
+                    relation.contact.as_str(),
+                    relation.name.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn representation_is_not_a_change() {
+        let previous = json!({
+            "id": "a1",
+            "fullName": "John Doe",
+            "tels": {"cell": "+420603123456"},
+            "emails": {},
+            "labels": {},
+            "modified": "2020-01-01T12:00:00Z",
+        });
+        let mut contact: Contact = serde_json::from_value(previous.clone()).unwrap();
+        assert!(!contact.touch_if_changed(&previous));
+        assert_eq!(
+            contact.modified(),
+            Some("2020-01-01T12:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn change_is_detected() {
+        let previous = json!({
+            "id": "a1",
+            "fullName": "John Doe",
+            "entityName": null,
+            "tels": {},
+            "emails": {},
+            "labels": {},
+        });
+        let mut contact: Contact = serde_json::from_value(previous.clone()).unwrap();
+        contact
+            .labels
+            .insert(String::from("nick"), String::from("jd"));
+        assert!(contact.touch_if_changed(&previous));
+        assert!(contact.modified().is_some());
+    }
+
+    #[test]
+    fn relations_added_and_removed() {
+        let mut john = person("john", "John Doe");
+        john.add_relation(String::from("spouse"), String::from("jane"), None)
+            .unwrap();
+        john.add_relation(String::from("spouse"), String::from("jane"), None)
+            .unwrap();
+        john.add_relation(String::from("friend"), String::from("jane"), None)
+            .unwrap();
+        assert!(john
+            .add_relation(String::from("friend"), String::from("john"), None)
+            .is_err());
+        assert_eq!(
+            related(&john),
+            [("spouse", "jane", None), ("friend", "jane", None)]
+        );
+
+        let (contacts, errors) =
+            Contacts::from_parts(vec![john, person("jane", "Jane Doe")], Vec::new());
+        assert!(errors.is_empty());
+        let mut john = contacts.get("john").unwrap().clone();
+        assert_eq!(
+            related(&john),
+            [
+                ("spouse", "jane", Some("Jane Doe")),
+                ("friend", "jane", Some("Jane Doe"))
+            ]
+        );
+
+        assert!(john.remove_relation("spouse", "jane"));
+        assert!(!john.remove_relation("spouse", "jane"));
+        assert!(!john.remove_relation("friend", "alice"));
+        assert_eq!(related(&john), [("friend", "jane", Some("Jane Doe"))]);
+    }
+
+    #[test]
+    fn relations_of_removed_contacts() {
+        let mut alice = person("alice", "Alice Roe");
+        for (kind, id) in [("friend", "john"), ("friend", "jd"), ("boss", "jane")] {
+            alice
+                .add_relation(kind.to_owned(), id.to_owned(), None)
+                .unwrap();
+        }
+        let mut john = person("john", "John Doe");
+        john.add_relation(String::from("friend"), String::from("jd"), None)
+            .unwrap();
+        let (mut contacts, _) = Contacts::from_parts(
+            vec![
+                alice,
+                john,
+                person("jd", "J. Doe"),
+                person("jane", "Jane Doe"),
+            ],
+            Vec::new(),
+        );
+
+        let (john, rest) = contacts.contacts_mut()[1..].split_at_mut(1);
+        john[0].merge(&mut rest[0]);
+        rest[1].delete();
+        contacts.remove_marked();
+
+        let ids: Vec<&str> = contacts.contacts().iter().map(Contact::id).collect();
+        assert_eq!(ids, ["alice", "john"]);
+        assert_eq!(
+            related(contacts.get("alice").unwrap()),
+            [("friend", "john", Some("John Doe"))]
+        );
+        assert!(contacts.get("john").unwrap().relations().is_empty());
+    }
+
+    fn group(name: &str, contacts: &[&str], groups: &[&str]) -> Group {
+        Group {
+            name: name.to_owned(),
+            contacts: contacts.iter().map(|id| id.to_string()).collect(),
+            groups: groups.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn members(contacts: &Contacts, name: &str) -> Vec<String> {
+        let mut members: Vec<String> = contacts.group_members(name).unwrap().into_iter().collect();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn nested_groups() {
+        let groups = vec![
+            group("all", &["alice"], &["family", "work"]),
+            group("family", &["jane", "john"], &[]),
+            group("work", &["john", "bob"], &["team"]),
+            group("team", &["carol"], &["work"]),
+            group("empty", &[], &["missing"]),
+        ];
+        let (mut contacts, _) = Contacts::from_parts(Vec::new(), groups);
+
+        assert_eq!(
+            members(&contacts, "all"),
+            ["alice", "bob", "carol", "jane", "john"]
+        );
+        assert_eq!(members(&contacts, "team"), ["bob", "carol", "john"]);
+        assert!(members(&contacts, "empty").is_empty());
+        assert!(contacts.group_members("missing").is_err());
+
+        assert!(contacts.create_group(String::from("work")).is_err());
+        contacts.create_group(String::from("friends")).unwrap();
+        assert!(members(&contacts, "friends").is_empty());
+        assert!(contacts.group_mut("enemies").is_err());
+    }
+
+    #[test]
+    fn group_members_of_removed_contacts() {
+        let (mut contacts, _) = Contacts::from_parts(
+            vec![
+                person("john", "John Doe"),
+                person("jd", "J. Doe"),
+                person("jane", "Jane Doe"),
+            ],
+            vec![
+                group("family", &["jd", "john", "jane"], &[]),
+                group("work", &["jd"], &[]),
+            ],
+        );
+
+        let (john, rest) = contacts.contacts_mut().split_at_mut(1);
+        john[0].merge(&mut rest[0]);
+        rest[1].delete();
+        contacts.remove_marked();
+
+        assert_eq!(contacts.groups()[0].contacts, ["john"]);
+        assert_eq!(contacts.groups()[1].contacts, ["john"]);
+    }
+
+    #[test]
+    fn tags_are_a_set() {
+        let mut john: Contact = serde_json::from_value(json!({
+            "id": "john",
+            "fullName": "John Doe",
+            "entityName": null,
+            "tels": {},
+            "emails": {},
+            "labels": {},
+            "tags": ["work", "project=beta", "work", "project=alpha"],
+        }))
+        .unwrap();
+        let tags: Vec<&str> = john.tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, ["project=alpha", "project=beta", "work"]);
+
+        let mut jd = person("jd", "J. Doe");
+        jd.tags.insert(String::from("family"));
+        jd.tags.insert(String::from("work"));
+        john.merge(&mut jd);
+        let tags: Vec<&str> = john.tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, ["family", "project=alpha", "project=beta", "work"]);
+
+        let serialized = serde_json::to_value(person("jane", "Jane Doe")).unwrap();
+        assert!(serialized.get("tags").is_none());
+    }
+
+    #[test]
+    fn preferred_item_moved_to_front() {
+        let mut john = person("john", "John Doe");
+        assert_eq!(john.preferred_email(), None);
+        for (key, address) in [
+            ("work", "john@acme.example"),
+            ("home", "john@example.com"),
+            ("old", "jd@example.com"),
+        ] {
+            john.emails.insert(key.to_owned(), address.to_owned());
+        }
+        assert_eq!(john.preferred_email(), Some("john@acme.example"));
+
+        assert!(set_preferred(&mut john.emails, "old"));
+        assert!(!set_preferred(&mut john.emails, "school"));
+        let keys: Vec<&str> = john.emails.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["old", "work", "home"]);
+        assert_eq!(john.preferred_email(), Some("jd@example.com"));
+
+        assert!(set_preferred(&mut john.emails, "old"));
+        let keys: Vec<&str> = john.emails.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["old", "work", "home"]);
+    }
+
+    #[test]
+    fn person_converted_to_entity_and_back() {
+        let mut contact = person("acme", "Acme");
+        contact
+            .set_organization(Some(String::from("Acme Group")))
+            .unwrap();
+        assert!(contact.convert_to_entity().is_err());
+        assert!(contact.convert_to_person().is_err());
+
+        contact.set_organization(None).unwrap();
+        contact.convert_to_entity().unwrap();
+        assert_eq!(contact.full_name(), None);
+        assert_eq!(contact.entity_name(), Some("Acme"));
+        assert_eq!(contact.validate(), None);
+        assert!(contact.convert_to_entity().is_err());
+        assert!(contact.set_title(Some(String::from("CEO"))).is_err());
+
+        contact.convert_to_person().unwrap();
+        assert_eq!(contact.full_name(), Some("Acme"));
+        assert_eq!(contact.entity_name(), None);
+        assert_eq!(contact.validate(), None);
+        contact.set_title(Some(String::from("CEO"))).unwrap();
+    }
+}
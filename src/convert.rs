@@ -0,0 +1,65 @@
+use crate::config::Config;
+use crate::storage::{ContactStore, StorageKind};
+use anyhow::{bail, Context, Result};
+use std::fs;
+
+/// Move all contacts from the configured storage to another storage and
+/// configure the new storage. Contacts in the previous storage are backed up.
+///
+/// # Arguments
+///
+/// * `target` - name of the new storage, see `Config::storage()`.
+pub fn convert_storage(target: &str) -> Result<()> {
+    let target_kind: StorageKind = target.parse()?;
+    let config = Config::load()?;
+    let source_kind = config.storage()?;
+    if source_kind == target_kind {
+        bail!("Contacts are already stored in {} storage.", target);
+    }
+
+    let source = source_kind.open(&config)?;
+    let target = target_kind.open(&config)?;
+    if target.exists()? {
+        bail!(
+            "Contacts already exist in {} storage. Remove them before the conversion.",
+            target_kind.name()
+        );
+    }
+
+    let (mut contacts, errors) = source.load()?;
+    for error in errors.iter() {
+        eprintln!("Warning: converting invalid {}", error);
+    }
+    target.save(&mut contacts)?;
+    for backup in source.backup()? {
+        eprintln!("Previous storage backed up to {}.", backup.display());
+    }
+    Config::set_storage(target_kind.name())?;
+
+    eprintln!(
+        "{} contacts converted to {} storage.",
+        contacts.contacts().len(),
+        target_kind.name()
+    );
+    Ok(())
+}
+
+/// Write all contacts to a contacts file (JSON), see `Contacts::load_from_path()`.
+/// Contacts files can be imported with `init --from`.
+pub fn export(store: &dyn ContactStore, path: &str) -> Result<()> {
+    let (mut contacts, errors) = store.load()?;
+    for error in errors.iter() {
+        eprintln!("Warning: exporting invalid {}", error);
+    }
+
+    contacts.sort();
+    let content = serde_json::to_string_pretty(&contacts)? + "\n";
+    fs::write(path, content).with_context(|| format!("Could not write {}.", path))?;
+
+    eprintln!(
+        "{} contacts exported to {}.",
+        contacts.contacts().len(),
+        path
+    );
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use crate::actions::MatchAction;
+use crate::contact::Contact;
+use anyhow::{bail, Result};
+use dialoguer::Confirm;
+
+pub struct DeleteContacts {}
+
+impl DeleteContacts {
+    pub fn new() -> Self {
+        DeleteContacts {}
+    }
+}
+
+impl MatchAction for DeleteContacts {
+    fn process(&self, contacts: Vec<&mut Contact>) -> Result<bool> {
+        if contacts.is_empty() {
+            bail!("No contact has been matched.");
+        }
+
+        for contact in contacts.iter() {
+            eprintln!("--------------------------------------------------");
+            eprint!("{}", contact);
+        }
+        eprintln!("--------------------------------------------------");
+
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Delete {} contacts?", contacts.len()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            return Ok(false);
+        }
+
+        for contact in contacts {
+            contact.delete();
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,443 @@
+use crate::config::Config;
+use crate::contact::{Contact, ContactError, Contacts};
+use crate::email;
+use crate::phone::Tel;
+use crate::storage::ContactStore;
+use anyhow::Result;
+use indexmap::IndexMap;
+use phonenumber::country;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+
+/// A problem found in the contacts file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Problem {
+    /// Index of the contact in the contacts file. Indices may change when
+    /// fixed contacts are saved, the ID identifies the contact reliably.
+    contact: usize,
+    id: String,
+    name: Option<String>,
+    /// Affected field, e.g. `emails.work`.
+    field: String,
+    message: String,
+    fixed: bool,
+}
+
+impl Problem {
+    fn new(index: usize, contact: &Contact, field: &str, message: String) -> Self {
+        Self {
+            contact: index,
+            id: contact.id().to_owned(),
+            name: contact.name().map(String::from),
+            field: field.to_owned(),
+            message,
+            fixed: false,
+        }
+    }
+
+    fn fixed(mut self, fixed: bool) -> Self {
+        self.fixed = fixed;
+        self
+    }
+}
+
+/// Check all contacts and report problems found.
+///
+/// # Arguments
+///
+/// * `json` - print the problems as a JSON array instead of human readable
+///   lines.
+/// * `fix` - automatically fix problems which can be fixed without a risk of
+///   data loss and save the contacts.
+pub fn doctor(store: &dyn ContactStore, json: bool, fix: bool) -> Result<()> {
+    let country = Config::load()?.default_country()?;
+    let (mut contacts, errors) = store.load()?;
+    let problems = check(&mut contacts, &errors, country, fix);
+
+    if problems.iter().any(|problem| problem.fixed) {
+        store.save(&mut contacts)?;
+    }
+
+    if json {
+        serde_json::to_writer_pretty(io::stdout(), &problems)?;
+        println!();
+    } else {
+        for problem in &problems {
+            println!(
+                "#{} {} ({}): {}: {}{}",
+                problem.contact,
+                problem.name.as_deref().unwrap_or("<unnamed>"),
+                problem.id,
+                problem.field,
+                problem.message,
+                if problem.fixed { " [fixed]" } else { "" }
+            );
+        }
+    }
+
+    let fixed = problems.iter().filter(|problem| problem.fixed).count();
+    match problems.len() {
+        0 => eprintln!("No problems found."),
+        n => eprintln!("{} problems found, {} fixed.", n, fixed),
+    }
+
+    Ok(())
+}
+
+/// Check all contacts and fix them if `fix` is true. Returns all problems
+/// found, including the given contact errors.
+fn check(
+    contacts: &mut Contacts,
+    errors: &[ContactError],
+    country: Option<country::Id>,
+    fix: bool,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for error in errors {
+        let contact = &contacts.contacts()[error.index];
+        let message = error.message.to_owned();
+        problems.push(Problem::new(error.index, contact, error.field, message));
+    }
+    for (index, contact) in contacts.contacts_mut().iter_mut().enumerate() {
+        check_contact(index, contact, country, fix, &mut problems);
+    }
+    check_duplicate_emails(contacts.contacts(), &mut problems);
+    problems
+}
+
+fn check_contact(
+    index: usize,
+    contact: &mut Contact,
+    country: Option<country::Id>,
+    fix: bool,
+    problems: &mut Vec<Problem>,
+) {
+    match (contact.full_name(), contact.entity_name()) {
+        (Some(full_name), None) if full_name.trim() != full_name => {
+            let trimmed = full_name.trim().to_owned();
+            let fixed = fix && !trimmed.is_empty() && contact.set_full_name(trimmed).is_ok();
+            problems.push(whitespace_problem(index, contact, "fullName").fixed(fixed));
+        }
+        (None, Some(entity_name)) if entity_name.trim() != entity_name => {
+            let trimmed = entity_name.trim().to_owned();
+            let fixed = fix && !trimmed.is_empty() && contact.set_entity_name(trimmed).is_ok();
+            problems.push(whitespace_problem(index, contact, "entityName").fixed(fixed));
+        }
+        _ => (),
+    }
+
+    check_keys(index, contact, "tels", |c| &mut c.tels, fix, problems);
+    check_keys(index, contact, "emails", |c| &mut c.emails, fix, problems);
+    check_keys(index, contact, "labels", |c| &mut c.labels, fix, problems);
+
+    let keys: Vec<String> = contact.tels.keys().cloned().collect();
+    for key in keys {
+        let field = format!("tels.{}", key);
+        let tel = contact.tels.get(&key).unwrap();
+
+        if tel.number().trim().is_empty() {
+            problems.push(empty_value_problem(index, contact, &field));
+            continue;
+        }
+
+        if tel.number().trim() != tel.number() {
+            let trimmed = Tel::parse(tel.number().trim().to_owned(), country);
+            if fix {
+                contact.tels.insert(key.clone(), trimmed);
+            }
+            problems.push(whitespace_problem(index, contact, &field).fixed(fix));
+        }
+
+        let tel = contact.tels.get(&key).unwrap();
+        if !tel.is_valid(country) {
+            let message = format!("invalid telephone number: {}", tel);
+            problems.push(Problem::new(index, contact, &field, message));
+        }
+
+        let tel = contact.tels.get_mut(&key).unwrap();
+        if !tel.is_normalized(country) {
+            if fix {
+                tel.normalize(country);
+            }
+            let message = format!("telephone number not normalized: {}", tel.number());
+            problems.push(Problem::new(index, contact, &field, message).fixed(fix));
+        }
+    }
+
+    let keys: Vec<String> = contact.emails.keys().cloned().collect();
+    for key in keys {
+        let field = format!("emails.{}", key);
+        let address = contact.emails.get(&key).unwrap().clone();
+
+        if address.trim().is_empty() {
+            problems.push(empty_value_problem(index, contact, &field));
+            continue;
+        }
+
+        if let Err(error) = email::validate(address.trim()) {
+            let message = format!("invalid e-mail address: {}", error);
+            problems.push(Problem::new(index, contact, &field, message));
+        }
+
+        let normalized = email::normalize(&address);
+        if normalized != address {
+            if fix {
+                contact.emails.insert(key.clone(), normalized);
+            }
+            let message = format!("e-mail address not normalized: {}", address);
+            problems.push(Problem::new(index, contact, &field, message).fixed(fix));
+        }
+    }
+
+    let keys: Vec<String> = contact.labels.keys().cloned().collect();
+    for key in keys {
+        let field = format!("labels.{}", key);
+        let value = contact.labels.get(&key).unwrap().clone();
+
+        if value.trim().is_empty() {
+            problems.push(empty_value_problem(index, contact, &field));
+        } else if value.trim() != value {
+            if fix {
+                contact.labels.insert(key.clone(), value.trim().to_owned());
+            }
+            problems.push(whitespace_problem(index, contact, &field).fixed(fix));
+        }
+    }
+}
+
+/// Check keys of a contact map for emptiness and surrounding whitespace.
+/// Keys with whitespace are trimmed if `fix` is true and the trimmed key is
+/// not already used.
+fn check_keys<V, F>(
+    index: usize,
+    contact: &mut Contact,
+    field: &str,
+    get_map: F,
+    fix: bool,
+    problems: &mut Vec<Problem>,
+) where
+    F: Fn(&mut Contact) -> &mut IndexMap<String, V>,
+{
+    let keys: Vec<String> = get_map(contact).keys().cloned().collect();
+    for key in keys {
+        let trimmed = key.trim();
+        if trimmed.is_empty() {
+            let message = String::from("empty key");
+            problems.push(Problem::new(index, contact, field, message));
+        } else if trimmed != key {
+            let map = get_map(contact);
+            let fixed = fix && !map.contains_key(trimmed);
+            if fixed {
+                let (position, _, value) = map.shift_remove_full(&key).unwrap();
+                map.shift_insert(position, trimmed.to_owned(), value);
+            }
+
+            let field = format!("{}.{}", field, key);
+            problems.push(whitespace_problem(index, contact, &field).fixed(fixed));
+        }
+    }
+}
+
+/// Report e-mail addresses used by more than one contact.
+fn check_duplicate_emails(contacts: &[Contact], problems: &mut Vec<Problem>) {
+    let mut first_use: HashMap<String, (usize, &str)> = HashMap::new();
+
+    for (index, contact) in contacts.iter().enumerate() {
+        for (key, address) in contact.emails.iter() {
+            let address = email::normalize(address);
+            match first_use.get(&address) {
+                Some(&(other, other_id)) if other != index => {
+                    let message = format!(
+                        "e-mail address {} is also used by contact #{} ({})",
+                        address, other, other_id
+                    );
+                    let field = format!("emails.{}", key);
+                    problems.push(Problem::new(index, contact, &field, message));
+                }
+                Some(_) => (),
+                None => {
+                    first_use.insert(address, (index, contact.id()));
+                }
+            }
+        }
+    }
+}
+
+fn whitespace_problem(index: usize, contact: &Contact, field: &str) -> Problem {
+    let message = String::from("leading or trailing whitespace");
+    Problem::new(index, contact, field, message)
+}
+
+fn empty_value_problem(index: usize, contact: &Contact, field: &str) -> Problem {
+    Problem::new(index, contact, field, String::from("empty value"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn contact(id: &str, full_name: &str, tels: Value, emails: Value, labels: Value) -> Value {
+        json!({
+            "id": id,
+            "fullName": full_name,
+            "entityName": null,
+            "tels": tels,
+            "emails": emails,
+            "labels": labels,
+        })
+    }
+
+    /// Check contacts and return them together with found problems.
+    fn run(contacts: Vec<Value>, fix: bool) -> (Contacts, Vec<Problem>) {
+        let contacts = serde_json::from_value(Value::from(contacts)).unwrap();
+        let (mut contacts, errors) = Contacts::from_parts(contacts, Vec::new());
+        let problems = check(&mut contacts, &errors, Some(country::Id::CZ), fix);
+        (contacts, problems)
+    }
+
+    fn fields(problems: &[Problem]) -> Vec<(&str, bool)> {
+        problems
+            .iter()
+            .map(|problem| (problem.field.as_str(), problem.fixed))
+            .collect()
+    }
+
+    #[test]
+    fn whitespace_trimmed() {
+        let contacts = vec![contact(
+            "a1",
+            " John Doe ",
+            json!({}),
+            json!({" work": "jd@example.com"}),
+            json!({"nick": " johnny "}),
+        )];
+
+        let (_, problems) = run(contacts.clone(), false);
+        assert_eq!(
+            fields(&problems),
+            [
+                ("fullName", false),
+                ("emails. work", false),
+                ("labels.nick", false)
+            ]
+        );
+
+        let (contacts, problems) = run(contacts, true);
+        assert_eq!(
+            fields(&problems),
+            [
+                ("fullName", true),
+                ("emails. work", true),
+                ("labels.nick", true)
+            ]
+        );
+        let contact = &contacts.contacts()[0];
+        assert_eq!(contact.full_name(), Some("John Doe"));
+        assert_eq!(contact.emails["work"], "jd@example.com");
+        assert_eq!(contact.labels["nick"], "johnny");
+    }
+
+    #[test]
+    fn trimmed_key_collision() {
+        let contacts = vec![contact(
+            "a1",
+            "John Doe",
+            json!({}),
+            json!({"work": "jd@example.com", "work ": "john@example.com"}),
+            json!({}),
+        )];
+
+        let (contacts, problems) = run(contacts, true);
+        assert_eq!(fields(&problems), [("emails.work ", false)]);
+        let keys: Vec<&str> = contacts.contacts()[0]
+            .emails
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys, ["work", "work "]);
+    }
+
+    #[test]
+    fn tel_normalized() {
+        let contacts = vec![contact(
+            "a1",
+            "John Doe",
+            json!({"mobile": "603 123 456", "home": "unknown"}),
+            json!({}),
+            json!({}),
+        )];
+
+        let (contacts, problems) = run(contacts, true);
+        assert_eq!(
+            fields(&problems),
+            [("tels.mobile", true), ("tels.home", false)]
+        );
+        assert_eq!(problems[1].message, "invalid telephone number: unknown");
+        let tels = &contacts.contacts()[0].tels;
+        assert_eq!(tels["mobile"].e164(), Some("+420603123456"));
+        assert!(tels["mobile"].is_normalized(Some(country::Id::CZ)));
+    }
+
+    #[test]
+    fn duplicate_email() {
+        let contacts = vec![
+            contact(
+                "a1",
+                "John Doe",
+                json!({}),
+                json!({"work": "jd@example.com"}),
+                json!({}),
+            ),
+            contact(
+                "b2",
+                "Jane Doe",
+                json!({}),
+                json!({"home": "jd@Example.com"}),
+                json!({}),
+            ),
+        ];
+
+        let (_, problems) = run(contacts, false);
+        let messages: Vec<(&str, &str)> = problems
+            .iter()
+            .map(|problem| (problem.id.as_str(), problem.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("b2", "e-mail address not normalized: jd@Example.com"),
+                (
+                    "b2",
+                    "e-mail address jd@example.com is also used by contact #0 (a1)"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let contacts = vec![contact(
+            "a1",
+            "John Doe",
+            json!({}),
+            json!({"work": ""}),
+            json!({}),
+        )];
+
+        let (_, problems) = run(contacts, true);
+        assert_eq!(
+            serde_json::to_value(&problems).unwrap(),
+            json!([{
+                "contact": 0,
+                "id": "a1",
+                "name": "John Doe",
+                "field": "emails.work",
+                "message": "empty value",
+                "fixed": false,
+            }])
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use crate::actions::MatchAction;
-use crate::contact::Contact;
+use crate::config::Config;
+use crate::contact::{self, Contact};
+use crate::email;
+use crate::phone::Tel;
 use anyhow::{bail, Result};
 use dialoguer::Input;
 use dialoguer::{theme::ColorfulTheme, Select};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use phonenumber::country;
 
 pub struct EditContact {}
 
@@ -22,8 +26,9 @@ impl MatchAction for EditContact {
             bail!("More than one contacts matched.");
         }
 
+        let country = Config::load()?.default_country()?;
         let contact = contacts.pop().unwrap();
-        edit_contact(contact)?;
+        edit_contact(contact, country)?;
         Ok(true)
     }
 }
@@ -32,18 +37,26 @@ enum Action {
     Finish,
     ChangeFullName,
     ChangeEntityName,
+    ConvertToEntity,
+    ConvertToPerson,
+    ChangeOrganization,
+    ChangeTitle,
     AddEmail,
     RemoveEmail,
     ChangeEmail,
+    PreferEmail,
     AddTel,
     RemoveTel,
     ChangeTel,
+    PreferTel,
     AddLabel,
     RemoveLabel,
     ChangeLabel,
+    AddTag,
+    RemoveTag,
 }
 
-fn edit_contact(contact: &mut Contact) -> Result<()> {
+fn edit_contact(contact: &mut Contact, country: Option<country::Id>) -> Result<()> {
     loop {
         eprintln!("--------------------------------------------------");
         eprint!("{}", contact);
@@ -57,15 +70,23 @@ fn edit_contact(contact: &mut Contact) -> Result<()> {
                 Action::Finish => String::from("finish"),
                 Action::ChangeFullName => String::from("change full name"),
                 Action::ChangeEntityName => String::from("change entity name"),
+                Action::ConvertToEntity => String::from("convert to entity"),
+                Action::ConvertToPerson => String::from("convert to person"),
+                Action::ChangeOrganization => String::from("change organization"),
+                Action::ChangeTitle => String::from("change title"),
                 Action::AddEmail => String::from("add email"),
                 Action::RemoveEmail => format!("remove email: {}", key.unwrap()),
                 Action::ChangeEmail => format!("change email: {}", key.unwrap()),
+                Action::PreferEmail => format!("make email preferred: {}", key.unwrap()),
                 Action::AddTel => String::from("add tel"),
                 Action::RemoveTel => format!("remove tel: {}", key.unwrap()),
                 Action::ChangeTel => format!("change tel: {}", key.unwrap()),
+                Action::PreferTel => format!("make tel preferred: {}", key.unwrap()),
                 Action::AddLabel => String::from("add label"),
                 Action::RemoveLabel => format!("remove label: {}", key.unwrap()),
                 Action::ChangeLabel => format!("change label: {}", key.unwrap()),
+                Action::AddTag => String::from("add tag"),
+                Action::RemoveTag => format!("remove tag: {}", key.unwrap()),
             };
 
             selections.push(prompt);
@@ -76,20 +97,30 @@ fn edit_contact(contact: &mut Contact) -> Result<()> {
 
         if contact.full_name().is_some() {
             add_action(Action::ChangeFullName, None);
+            add_action(Action::ChangeOrganization, None);
+            add_action(Action::ChangeTitle, None);
+            add_action(Action::ConvertToEntity, None);
         } else {
             add_action(Action::ChangeEntityName, None);
+            add_action(Action::ConvertToPerson, None);
         }
 
         add_action(Action::AddEmail, None);
-        for key in contact.emails.keys() {
+        for (index, key) in contact.emails.keys().enumerate() {
             add_action(Action::RemoveEmail, Some(key));
             add_action(Action::ChangeEmail, Some(key));
+            if index > 0 {
+                add_action(Action::PreferEmail, Some(key));
+            }
         }
 
         add_action(Action::AddTel, None);
-        for key in contact.tels.keys() {
+        for (index, key) in contact.tels.keys().enumerate() {
             add_action(Action::RemoveTel, Some(key));
             add_action(Action::ChangeTel, Some(key));
+            if index > 0 {
+                add_action(Action::PreferTel, Some(key));
+            }
         }
 
         add_action(Action::AddLabel, None);
@@ -98,6 +129,11 @@ fn edit_contact(contact: &mut Contact) -> Result<()> {
             add_action(Action::ChangeLabel, Some(key));
         }
 
+        add_action(Action::AddTag, None);
+        for tag in contact.tags.iter() {
+            add_action(Action::RemoveTag, Some(tag));
+        }
+
         let selection = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Select action")
             .default(0)
@@ -123,30 +159,77 @@ fn edit_contact(contact: &mut Contact) -> Result<()> {
                     .unwrap();
                 contact.set_entity_name(entity_name)?;
             }
-            Action::AddEmail => prompt_key_value("email", &mut contact.emails),
+            Action::ConvertToEntity => {
+                if let Err(error) = contact.convert_to_entity() {
+                    eprintln!("{}", error);
+                }
+            }
+            Action::ConvertToPerson => {
+                if let Err(error) = contact.convert_to_person() {
+                    eprintln!("{}", error);
+                }
+            }
+            Action::ChangeOrganization => {
+                contact.set_organization(prompt_optional(
+                    "Organization [empty to remove]",
+                    contact.organization(),
+                ))?;
+            }
+            Action::ChangeTitle => {
+                contact.set_title(prompt_optional("Title [empty to remove]", contact.title()))?;
+            }
+            Action::AddEmail => prompt_key_value("email", &mut contact.emails, email::parse),
             Action::RemoveEmail => {
-                contact.emails.remove(&key.unwrap());
+                contact.emails.shift_remove(&key.unwrap());
+            }
+            Action::ChangeEmail => {
+                prompt_change_value("email", &mut contact.emails, key.unwrap(), email::parse)
             }
-            Action::ChangeEmail => prompt_change_value("email", &mut contact.emails, key.unwrap()),
-            Action::AddTel => prompt_key_value("telephone number", &mut contact.tels),
+            Action::PreferEmail => {
+                contact::set_preferred(&mut contact.emails, &key.unwrap());
+            }
+            Action::AddTel => prompt_key_value("telephone number", &mut contact.tels, |value| {
+                Ok(parse_tel(value, country))
+            }),
             Action::RemoveTel => {
-                contact.tels.remove(&key.unwrap());
+                contact.tels.shift_remove(&key.unwrap());
             }
-            Action::ChangeTel => {
-                prompt_change_value("telephone number", &mut contact.tels, key.unwrap())
+            Action::ChangeTel => prompt_change_value(
+                "telephone number",
+                &mut contact.tels,
+                key.unwrap(),
+                |value| Ok(parse_tel(value, country)),
+            ),
+            Action::PreferTel => {
+                contact::set_preferred(&mut contact.tels, &key.unwrap());
             }
-            Action::AddLabel => prompt_key_value("label", &mut contact.labels),
+            Action::AddLabel => prompt_key_value("label", &mut contact.labels, Ok),
             Action::RemoveLabel => {
-                contact.labels.remove(&key.unwrap());
+                contact.labels.shift_remove(&key.unwrap());
+            }
+            Action::ChangeLabel => {
+                prompt_change_value("label", &mut contact.labels, key.unwrap(), Ok)
+            }
+            Action::AddTag => {
+                let tag = Input::<String>::new()
+                    .with_prompt("tag")
+                    .interact()
+                    .unwrap();
+                contact.tags.insert(tag);
+            }
+            Action::RemoveTag => {
+                contact.tags.remove(&key.unwrap());
             }
-            Action::ChangeLabel => prompt_change_value("label", &mut contact.labels, key.unwrap()),
         }
     }
 }
 
-fn prompt_key_value(name: &str, map: &mut HashMap<String, String>) {
+fn prompt_key_value<V, F>(name: &str, map: &mut IndexMap<String, V>, convert: F)
+where
+    F: Fn(String) -> Result<V>,
+{
     let key = Input::<String>::new()
-        .with_prompt(&format!("{} name", name))
+        .with_prompt(format!("{} name", name))
         .interact()
         .unwrap();
 
@@ -156,10 +239,44 @@ fn prompt_key_value(name: &str, map: &mut HashMap<String, String>) {
     }
 
     let value = Input::<String>::new().with_prompt(name).interact().unwrap();
-    map.insert(key, value);
+    match convert(value) {
+        Ok(value) => {
+            map.insert(key, value);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
 }
 
-fn prompt_change_value(name: &str, map: &mut HashMap<String, String>, key: String) {
+fn prompt_change_value<V, F>(name: &str, map: &mut IndexMap<String, V>, key: String, convert: F)
+where
+    F: Fn(String) -> Result<V>,
+{
     let value = Input::<String>::new().with_prompt(name).interact().unwrap();
-    map.insert(key, value);
+    match convert(value) {
+        Ok(value) => {
+            map.insert(key, value);
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+fn prompt_optional(name: &str, current: Option<&str>) -> Option<String> {
+    let value = Input::<String>::new()
+        .with_prompt(name)
+        .with_initial_text(current.unwrap_or(""))
+        .allow_empty(true)
+        .interact()
+        .unwrap();
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn parse_tel(value: String, country: Option<country::Id>) -> Tel {
+    let tel = Tel::parse(value, country);
+    tel.warn_if_invalid(country);
+    tel
 }
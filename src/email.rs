@@ -0,0 +1,137 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Maximum length of the local part (before `@`) of an e-mail address.
+const MAX_LOCAL_LENGTH: usize = 64;
+/// Maximum length of a whole e-mail address.
+const MAX_LENGTH: usize = 254;
+
+/// Regexes are compiled on first use, see `validate()`.
+static DOT_ATOM: OnceLock<Regex> = OnceLock::new();
+static QUOTED: OnceLock<Regex> = OnceLock::new();
+static LABEL: OnceLock<Regex> = OnceLock::new();
+
+/// Check that an e-mail address is syntactically valid. This follows the
+/// `addr-spec` of RFC 5322 without obsolete syntax, comments and domain
+/// literals. Non-ASCII characters are allowed (RFC 6531).
+pub fn validate(address: &str) -> Result<()> {
+    let (local, domain) = match address.rfind('@') {
+        Some(index) => (&address[..index], &address[index + 1..]),
+        None => bail!("E-mail address {} does not contain @.", address),
+    };
+
+    if address.len() > MAX_LENGTH {
+        bail!(
+            "E-mail address {} is longer than {} bytes.",
+            address,
+            MAX_LENGTH
+        );
+    }
+    if local.is_empty() || local.len() > MAX_LOCAL_LENGTH {
+        bail!(
+            "Local part of e-mail address {} must be between 1 and {} bytes long.",
+            address,
+            MAX_LOCAL_LENGTH
+        );
+    }
+
+    let dot_atom = DOT_ATOM.get_or_init(|| {
+        Regex::new(r"^[\w!#$%&'*+/=?^`{|}~-]+(\.[\w!#$%&'*+/=?^`{|}~-]+)*$").unwrap()
+    });
+    let quoted = QUOTED.get_or_init(|| Regex::new(r#"^"([^"\\\s]|\\[^\s]| )*"$"#).unwrap());
+    if !dot_atom.is_match(local) && !quoted.is_match(local) {
+        bail!("Local part of e-mail address {} is not valid.", address);
+    }
+
+    let label =
+        LABEL.get_or_init(|| Regex::new(r"^[\p{L}\p{N}]([\p{L}\p{N}-]*[\p{L}\p{N}])?$").unwrap());
+    if !domain.contains('.') || !domain.split('.').all(|part| label.is_match(part)) {
+        bail!("Domain of e-mail address {} is not valid.", address);
+    }
+
+    Ok(())
+}
+
+/// Return normalized form of an e-mail address: surrounding whitespace is
+/// removed and the domain is lowercased. The local part is kept intact since
+/// it might be case sensitive.
+pub fn normalize(address: &str) -> String {
+    let address = address.trim();
+    match address.rfind('@') {
+        Some(index) => format!(
+            "{}@{}",
+            &address[..index],
+            address[index + 1..].to_lowercase()
+        ),
+        None => address.to_owned(),
+    }
+}
+
+/// Validate and normalize an e-mail address entered by the user.
+pub fn parse(address: String) -> Result<String> {
+    let address = normalize(&address);
+    validate(&address)?;
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_addresses() {
+        validate("john.doe@example.com").unwrap();
+        validate("john+tag@mail.example.com").unwrap();
+        validate(r#""john doe"@example.com"#).unwrap();
+        validate(r#""john\"doe"@example.com"#).unwrap();
+        validate("jiří@příklad.cz").unwrap();
+        validate("user@xn--pklad-zsa96e.cz").unwrap();
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        let invalid = [
+            "john.doe.example.com",
+            "@example.com",
+            "john..doe@example.com",
+            ".john@example.com",
+            "john doe@example.com",
+            r#""john"doe"@example.com"#,
+            "john@localhost",
+            "john@-example.com",
+            "john@example-.com",
+            "john@example..com",
+            "john@exa_mple.com",
+        ];
+        for address in invalid {
+            assert!(validate(address).is_err(), "{}", address);
+        }
+
+        let error = validate("john.doe.example.com").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "E-mail address john.doe.example.com does not contain @."
+        );
+    }
+
+    #[test]
+    fn too_long_addresses() {
+        let local = "a".repeat(MAX_LOCAL_LENGTH + 1);
+        assert!(validate(&format!("{}@example.com", local)).is_err());
+        let domain = format!("{}.com", "a".repeat(MAX_LENGTH));
+        assert!(validate(&format!("john@{}", domain)).is_err());
+    }
+
+    #[test]
+    fn normalized_domain() {
+        assert_eq!(normalize(" John.Doe@Example.COM "), "John.Doe@example.com");
+        assert_eq!(normalize("Jiří@PŘÍKLAD.cz"), "Jiří@příklad.cz");
+        assert_eq!(normalize("John"), "John");
+        assert_eq!(
+            parse(String::from("John@Example.com")).unwrap(),
+            "John@example.com"
+        );
+        assert!(parse(String::from("John")).is_err());
+    }
+}
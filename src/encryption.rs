@@ -0,0 +1,224 @@
+use crate::config::Config;
+use crate::contact::Contacts;
+use crate::index;
+use crate::storage::StorageKind;
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::{ExposeSecret, SecretString};
+use age::{x25519, Decryptor, Encryptor, Identity, IdentityFile, NoCallbacks, Recipient};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use dialoguer::Password;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// First line of an encrypted file. Contacts are encrypted with age and
+/// ASCII armored, so that an encrypted contacts file is still a text file
+/// which git can store and diff.
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Key of the current run. It is loaded on first use so that the passphrase
+/// is asked for at most once.
+static KEY: OnceLock<Key> = OnceLock::new();
+
+enum Key {
+    /// Content of an age identity file, see `Config::encryption_key_file()`.
+    Identities(String),
+    Passphrase(SecretString),
+}
+
+impl Key {
+    /// Get the key of the current run.
+    ///
+    /// # Arguments
+    ///
+    /// * `confirm` - ask for the passphrase twice if it has not been entered
+    ///   yet.
+    fn get(confirm: bool) -> Result<&'static Self> {
+        if let Some(key) = KEY.get() {
+            return Ok(key);
+        }
+
+        let key = match Config::load()?.encryption_key_file() {
+            Some(path) => Key::Identities(
+                fs::read_to_string(&path)
+                    .with_context(|| format!("Could not read key file {}.", path.display()))?,
+            ),
+            None => {
+                let mut prompt = Password::new();
+                prompt.with_prompt("Contacts passphrase");
+                if confirm {
+                    prompt.with_confirmation("Repeat passphrase", "Passphrases do not match.");
+                }
+                let passphrase = prompt.interact().context("Could not read passphrase.")?;
+                Key::Passphrase(SecretString::from(passphrase))
+            }
+        };
+        Ok(KEY.get_or_init(|| key))
+    }
+
+    fn identity_file(content: &str) -> Result<IdentityFile<NoCallbacks>> {
+        IdentityFile::from_buffer(content.as_bytes()).context("Invalid key file.")
+    }
+
+    fn recipients(&self) -> Result<Vec<Box<dyn Recipient + Send>>> {
+        match self {
+            Key::Identities(content) => Self::identity_file(content)?
+                .to_recipients()
+                .context("Invalid key file."),
+            Key::Passphrase(passphrase) => Ok(vec![Box::new(age::scrypt::Recipient::new(
+                SecretString::from(passphrase.expose_secret()),
+            ))]),
+        }
+    }
+
+    fn identities(&self) -> Result<Vec<Box<dyn Identity>>> {
+        match self {
+            Key::Identities(content) => Self::identity_file(content)?
+                .into_identities()
+                .context("Invalid key file."),
+            Key::Passphrase(passphrase) => Ok(vec![Box::new(age::scrypt::Identity::new(
+                SecretString::from(passphrase.expose_secret()),
+            ))]),
+        }
+    }
+}
+
+/// Returns true if content of a file is encrypted.
+pub fn is_encrypted(content: &str) -> bool {
+    content.trim_start().starts_with(ARMOR_BEGIN)
+}
+
+/// Returns true if a file exists and is encrypted.
+pub fn is_encrypted_file(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| is_encrypted(&content))
+}
+
+/// Encrypt content of a file with the configured key.
+pub fn encrypt(plaintext: &str) -> Result<String> {
+    let recipients = Key::get(false)?.recipients()?;
+    let encryptor = Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient.as_ref() as &dyn Recipient),
+    )
+    .context("The key file does not contain any key.")?;
+
+    let mut encrypted = Vec::new();
+    let armored = ArmoredWriter::wrap_output(&mut encrypted, Format::AsciiArmor)?;
+    let mut writer = encryptor.wrap_output(armored)?;
+    writer.write_all(plaintext.as_bytes())?;
+    writer
+        .finish()
+        .and_then(|armored| armored.finish())
+        .context("Error while encrypting contacts.")?;
+    Ok(String::from_utf8(encrypted)? + "\n")
+}
+
+/// Decrypt content of an encrypted file with the configured key.
+pub fn decrypt(ciphertext: &str) -> Result<String> {
+    let identities = Key::get(false)?.identities()?;
+    let decryptor = Decryptor::new_buffered(ArmoredReader::new(ciphertext.trim().as_bytes()))
+        .context("Invalid encrypted file.")?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref()))
+        .context("Could not decrypt contacts, check the key file or passphrase.")?;
+
+    let mut plaintext = String::new();
+    reader
+        .read_to_string(&mut plaintext)
+        .context("Could not decrypt contacts.")?;
+    Ok(plaintext)
+}
+
+/// Decrypt content of a file if it is encrypted, see `is_encrypted()`.
+pub fn decrypt_if_encrypted(content: String) -> Result<String> {
+    if is_encrypted(&content) {
+        decrypt(&content)
+    } else {
+        Ok(content)
+    }
+}
+
+/// Encrypt the contacts file. If a key file is configured but does not
+/// exist, a new key is generated. The search index, which is not encrypted,
+/// is removed.
+pub fn encrypt_contacts() -> Result<()> {
+    let config = Config::load()?;
+    let path = contacts_file(&config)?;
+    let content = fs::read_to_string(&path)
+        .context("Could not load contacts. Make sure contacts are initialized.")?;
+    if is_encrypted(&content) {
+        bail!("Contacts are already encrypted.");
+    }
+    serde_json::from_str::<serde_json::Value>(&content)
+        .with_context(|| format!("Invalid contacts file {}.", path.display()))?;
+
+    if let Some(key_file) = config.encryption_key_file() {
+        if !key_file.exists() {
+            generate_key(&key_file)?;
+            eprintln!(
+                "New key written to {}. Keep a copy of it, contacts cannot be \
+                 decrypted without it.",
+                key_file.display()
+            );
+        }
+    }
+
+    Key::get(true)?;
+    fs::write(&path, encrypt(&content)?).context("Error while saving contacts.")?;
+    index::remove()?;
+    eprintln!("Contacts encrypted.");
+    Ok(())
+}
+
+/// Replace the encrypted contacts file with its decrypted content.
+pub fn decrypt_contacts() -> Result<()> {
+    let path = contacts_file(&Config::load()?)?;
+    let content = fs::read_to_string(&path)
+        .context("Could not load contacts. Make sure contacts are initialized.")?;
+    if !is_encrypted(&content) {
+        bail!("Contacts are not encrypted.");
+    }
+
+    fs::write(&path, decrypt(&content)?).context("Error while saving contacts.")?;
+    eprintln!("Contacts decrypted.");
+    Ok(())
+}
+
+/// Path of the contacts file. Only `file` storage can be encrypted.
+fn contacts_file(config: &Config) -> Result<PathBuf> {
+    if config.storage()? != StorageKind::File {
+        bail!("Only contacts in file storage can be encrypted.");
+    }
+    Contacts::get_contacts_file(false)
+}
+
+/// Write a new age identity file readable only by its owner.
+fn generate_key(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create directory {}.", dir.display()))?;
+    }
+
+    let identity = x25519::Identity::generate();
+    let content = format!(
+        "# created: {}\n# public key: {}\n{}\n",
+        Local::now().to_rfc3339(),
+        identity.to_public(),
+        identity.to_string().expose_secret()
+    );
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Could not create key file {}.", path.display()))?;
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Could not write key file {}.", path.display()))
+}
@@ -2,7 +2,7 @@ use crate::contact::Contacts;
 use anyhow::{bail, Context, Result};
 use std::ffi::OsStr;
 use std::iter::IntoIterator;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 pub fn call<S, I>(args: I) -> Result<()>
 where
@@ -32,3 +32,54 @@ where
 
     Ok(())
 }
+
+/// Call git in the directory with contacts and return its standard output.
+/// Standard error output of git is passed through.
+pub fn output<S, I>(args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let contacts_dir = Contacts::get_contacts_dir()?;
+    if !contacts_dir.is_dir() {
+        bail!("Directory with contacts does not yet exist. You have to initialize it first.");
+    }
+
+    let output = Command::new("git")
+        .current_dir(contacts_dir)
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to call git.")?;
+
+    if !output.status.success() {
+        let exit_code = match output.status.code() {
+            Some(code) => format!("{}", code),
+            None => "None".to_owned(),
+        };
+        bail!("git finished with non-success exit code: {}", exit_code);
+    }
+
+    String::from_utf8(output.stdout).context("git output is not valid UTF-8.")
+}
+
+/// Call git in the directory with contacts and return whether it finished
+/// successfully. Standard output of git is discarded.
+pub fn succeeds<S, I>(args: I) -> Result<bool>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let contacts_dir = Contacts::get_contacts_dir()?;
+    if !contacts_dir.is_dir() {
+        bail!("Directory with contacts does not yet exist. You have to initialize it first.");
+    }
+
+    let exit_status = Command::new("git")
+        .current_dir(contacts_dir)
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to call git.")?;
+    Ok(exit_status.success())
+}
@@ -0,0 +1,184 @@
+use crate::contact::Contacts;
+use crate::storage::ContactStore;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+/// Create a new empty group.
+pub fn create(store: &dyn ContactStore, name: &str) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+    contacts.create_group(name.to_owned())?;
+    store.save(&mut contacts)?;
+
+    eprintln!("Group successfully created.");
+    Ok(())
+}
+
+/// Add contacts or a nested group to a group.
+///
+/// # Arguments
+///
+/// * `name` - name of the group.
+/// * `name_regex` - a regular expression. All contacts whose full name or
+///   entity name matches the regex are added to the group.
+/// * `nested` - name of a group to be nested in the group.
+pub fn add(
+    store: &dyn ContactStore,
+    name: &str,
+    name_regex: Option<&str>,
+    nested: Option<&str>,
+) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+
+    let ids = match name_regex {
+        Some(name_regex) => find_ids(&contacts, name_regex)?,
+        None => Vec::new(),
+    };
+    if let Some(nested) = nested {
+        if nested == name {
+            bail!("A group cannot be nested in itself.");
+        }
+        contacts.group_mut(nested)?;
+    }
+
+    let group = contacts.group_mut(name)?;
+    for id in ids {
+        if !group.contacts.contains(&id) {
+            group.contacts.push(id);
+        }
+    }
+    if let Some(nested) = nested {
+        if !group.groups.iter().any(|other| other == nested) {
+            group.groups.push(nested.to_owned());
+        }
+    }
+
+    store.save(&mut contacts)?;
+
+    eprintln!("Group successfully updated.");
+    Ok(())
+}
+
+/// Remove contacts or a nested group from a group. See `add()`.
+pub fn remove(
+    store: &dyn ContactStore,
+    name: &str,
+    name_regex: Option<&str>,
+    nested: Option<&str>,
+) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+
+    let ids = match name_regex {
+        Some(name_regex) => find_ids(&contacts, name_regex)?,
+        None => Vec::new(),
+    };
+
+    let group = contacts.group_mut(name)?;
+    group.contacts.retain(|id| !ids.contains(id));
+    if let Some(nested) = nested {
+        group.groups.retain(|other| other != nested);
+    }
+
+    store.save(&mut contacts)?;
+
+    eprintln!("Group successfully updated.");
+    Ok(())
+}
+
+/// Print all groups or names of all members of a group.
+///
+/// # Arguments
+///
+/// * `name` - name of the group whose members are printed. All groups are
+///   printed if it is None.
+pub fn list(store: &dyn ContactStore, name: Option<&str>) -> Result<()> {
+    let contacts = store.load_valid()?;
+
+    match name {
+        Some(name) => {
+            let members = contacts.group_members(name)?;
+            for contact in contacts.contacts() {
+                if members.contains(contact.id()) {
+                    println!("{}", contact.name().unwrap_or("<unnamed>"));
+                }
+            }
+        }
+        None => {
+            for group in contacts.groups() {
+                let members = contacts.group_members(&group.name)?;
+                println!("{} ({} members)", group.name, members.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Return IDs of all contacts whose name matches a regular expression.
+fn find_ids(contacts: &Contacts, name_regex: &str) -> Result<Vec<String>> {
+    let regex = Regex::new(name_regex).context("Invalid name regex.")?;
+
+    let ids: Vec<String> = contacts
+        .contacts()
+        .iter()
+        .filter(|contact| contact.name().is_some_and(|name| regex.is_match(name)))
+        .map(|contact| contact.id().to_owned())
+        .collect();
+
+    if ids.is_empty() {
+        bail!("No contact matches {}.", name_regex);
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    fn store() -> MemoryStore {
+        let contact = |id: &str, full_name: &str| {
+            json!({
+                "id": id,
+                "fullName": full_name,
+                "entityName": null,
+                "tels": {},
+                "emails": {},
+                "labels": {},
+            })
+        };
+        MemoryStore::with_contacts(json!({
+            "contacts": [
+                contact("john", "John Doe"),
+                contact("jane", "Jane Doe"),
+                contact("alice", "Alice Roe"),
+            ],
+        }))
+    }
+
+    #[test]
+    fn add_and_remove_members() {
+        let store = store();
+        create(&store, "family").unwrap();
+        assert!(create(&store, "family").is_err());
+        create(&store, "friends").unwrap();
+
+        add(&store, "family", Some("Doe"), None).unwrap();
+        add(&store, "friends", Some("Alice"), Some("family")).unwrap();
+        assert!(add(&store, "friends", None, Some("friends")).is_err());
+        assert!(add(&store, "friends", None, Some("colleagues")).is_err());
+
+        let contacts = store.load_valid().unwrap();
+        let mut members: Vec<String> = contacts
+            .group_members("friends")
+            .unwrap()
+            .into_iter()
+            .collect();
+        members.sort();
+        assert_eq!(members, ["alice", "jane", "john"]);
+
+        remove(&store, "friends", Some("Alice"), Some("family")).unwrap();
+        let contacts = store.load_valid().unwrap();
+        assert!(contacts.group_members("friends").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::actions::MatchAction;
+use crate::config::Config;
+use crate::contact::Contact;
+use crate::encryption;
+use crate::git;
+use crate::storage::{self, StorageKind};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+pub struct History {
+    revert: Option<String>,
+}
+
+impl History {
+    /// # Arguments
+    ///
+    /// * `revert` - a git revision. If given, the contact is restored to its
+    ///   version from the revision instead of printing its history.
+    ///
+    /// Fails if the configured storage does not store contacts in files
+    /// tracked by git.
+    pub fn new(revert: Option<String>) -> Result<Self> {
+        let storage = Config::load()?.storage()?;
+        if !matches!(storage, StorageKind::File | StorageKind::Directory) {
+            bail!("History is not supported for {} storage.", storage.name());
+        }
+        Ok(History { revert })
+    }
+}
+
+impl MatchAction for History {
+    fn process(&self, mut contacts: Vec<&mut Contact>) -> Result<bool> {
+        if contacts.is_empty() {
+            bail!("No contact has been matched.");
+        }
+        if contacts.len() > 1 {
+            bail!("More than one contacts matched.");
+        }
+
+        let contact = contacts.pop().unwrap();
+        match &self.revert {
+            Some(revision) => {
+                revert(contact, revision)?;
+                Ok(true)
+            }
+            None => {
+                print_history(contact.id())?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// A committed version of a contact.
+struct Version {
+    commit: String,
+    author: String,
+    date: String,
+    /// Serialized contact or None if the contact did not exist in the
+    /// commit.
+    contact: Option<Value>,
+}
+
+/// Print all committed changes of a contact, the most recent first.
+fn print_history(id: &str) -> Result<()> {
+    let versions = load_versions(id)?;
+
+    let mut previous: Option<&Value> = None;
+    let mut entries = Vec::new();
+    for version in versions.iter() {
+        let changes = diff(previous, version.contact.as_ref());
+        if !changes.is_empty() {
+            entries.push((version, changes));
+        }
+        previous = version.contact.as_ref();
+    }
+
+    if entries.is_empty() {
+        eprintln!("The contact has not been committed yet.");
+    }
+
+    for (version, changes) in entries.iter().rev() {
+        println!(
+            "{} {} {}",
+            &version.commit[..7],
+            version.date,
+            version.author
+        );
+        for change in changes {
+            println!("  {}", change);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace a contact with its version from a git revision.
+fn revert(contact: &mut Contact, revision: &str) -> Result<()> {
+    let commit = git::output([
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{}^{{commit}}", revision),
+    ])
+    .with_context(|| format!("Unknown revision {}.", revision))?;
+
+    let path = storage::open()?.contact_path(contact.id())?;
+    let version = match load_contact(commit.trim(), &path, contact.id())? {
+        Some(version) => version,
+        None => bail!("The contact does not exist in revision {}.", revision),
+    };
+
+    *contact = serde_json::from_value(version)
+        .with_context(|| format!("Invalid contact in revision {}.", revision))?;
+    eprintln!("Contact reverted to its version from {}.", revision);
+    Ok(())
+}
+
+/// Load all committed versions of a contact, the oldest first.
+fn load_versions(id: &str) -> Result<Vec<Version>> {
+    let path = storage::open()?.contact_path(id)?;
+    let log = git::output([
+        "log",
+        "--reverse",
+        "--first-parent",
+        "--diff-filter=ACDMRT",
+        "--date=format:%Y-%m-%d %H:%M",
+        "--format=%H%x09%ad%x09%an",
+        "--",
+        &path,
+    ])?;
+
+    let mut versions = Vec::new();
+    for line in log.lines() {
+        let mut parts = line.splitn(3, '\t');
+        let (commit, date, author) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(commit), Some(date), Some(author)) => (commit, date, author),
+            _ => bail!("Unexpected git log output: {}", line),
+        };
+
+        versions.push(Version {
+            commit: commit.to_owned(),
+            author: author.to_owned(),
+            date: date.to_owned(),
+            contact: load_contact(commit, &path, id)?,
+        });
+    }
+
+    Ok(versions)
+}
+
+/// Load a serialized contact from a file in a commit. The file is either a
+/// contacts file or a file with a single contact. Returns None if the
+/// contact does not exist in the commit.
+fn load_contact(commit: &str, path: &str, id: &str) -> Result<Option<Value>> {
+    let object = format!("{}:{}", commit, path);
+    if !git::succeeds(["cat-file", "-e", &object])? {
+        return Ok(None);
+    }
+
+    let content = encryption::decrypt_if_encrypted(git::output(["show", &object])?)?;
+    let mut contacts: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid contacts file in commit {}.", commit))?;
+    if contacts.get("contacts").is_none() {
+        return Ok(Some(contacts).filter(|contact| contact["id"].as_str() == Some(id)));
+    }
+
+    let contacts = match contacts["contacts"].as_array_mut() {
+        Some(contacts) => contacts,
+        None => return Ok(None),
+    };
+    let index = contacts
+        .iter()
+        .position(|contact| contact["id"].as_str() == Some(id));
+    Ok(index.map(|index| contacts.swap_remove(index)))
+}
+
+/// Return human readable changes between two versions of a contact.
+fn diff(previous: Option<&Value>, current: Option<&Value>) -> Vec<String> {
+    match (previous, current) {
+        (None, None) => Vec::new(),
+        (None, Some(_)) => vec![String::from("created")],
+        (Some(_), None) => vec![String::from("deleted")],
+        (Some(previous), Some(current)) => {
+            let mut changes = Vec::new();
+            diff_values("", previous, current, &mut changes);
+            changes
+        }
+    }
+}
+
+/// Compare two JSON values and push changed fields to `changes`. Objects are
+/// compared field by field.
+fn diff_values(path: &str, previous: &Value, current: &Value, changes: &mut Vec<String>) {
+    if previous == current {
+        return;
+    }
+
+    match (previous.as_object(), current.as_object()) {
+        (Some(previous), Some(current)) => {
+            let removed = previous.keys().filter(|key| !current.contains_key(*key));
+            for key in current.keys().chain(removed) {
+                if path.is_empty() && key == "modified" {
+                    continue;
+                }
+
+                let path = if path.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let previous = previous.get(key).unwrap_or(&Value::Null);
+                let current = current.get(key).unwrap_or(&Value::Null);
+                diff_values(&path, previous, current, changes);
+            }
+        }
+        _ => changes.push(format!("{}: {} -> {}", path, previous, current)),
+    }
+}
@@ -0,0 +1,84 @@
+use crate::abook;
+use crate::config::Config;
+use crate::contact::{Contact, Contacts};
+use crate::khard;
+use crate::storage::ContactStore;
+use anyhow::{bail, Context, Result};
+use std::fs;
+
+/// Import contacts from a file of another application and add them to the
+/// stored contacts. Imported contacts which share an e-mail address with a
+/// stored contact are reported, they can be merged with `search ... merge`.
+///
+/// # Arguments
+///
+/// * `store` - storage of the contacts.
+/// * `format` - format of the file, `abook` (see `abook::parse()`) or
+///   `khard-yaml` (see `khard::parse()`).
+/// * `path` - path to the file.
+/// * `dry_run` - only print the contacts which would be imported.
+pub fn import(store: &dyn ContactStore, format: &str, path: &str, dry_run: bool) -> Result<()> {
+    let content = fs::read_to_string(path).with_context(|| format!("Could not read {}.", path))?;
+    let country = Config::load()?.default_country()?;
+    let imported = match format {
+        "abook" => abook::parse(&content, country),
+        "khard-yaml" => khard::parse(&content, country),
+        _ => bail!("Unknown import format: {}", format),
+    }
+    .with_context(|| format!("Could not import {}.", path))?;
+    for contact in imported.iter() {
+        if let Some((field, message)) = contact.validate() {
+            bail!(
+                "Invalid contact {} ({}): {}",
+                contact.name().unwrap_or_default(),
+                field,
+                message
+            );
+        }
+    }
+
+    if dry_run {
+        // Nothing is loaded by a dry run of an import to a new storage.
+        if store.exists()? {
+            warn_about_duplicates(&store.load_valid()?, &imported);
+        }
+        for contact in imported.iter() {
+            println!("--------------------------------------------------");
+            print!("{}", contact);
+        }
+        println!("--------------------------------------------------");
+        eprintln!(
+            "{} contacts would be imported from {}.",
+            imported.len(),
+            path
+        );
+        return Ok(());
+    }
+
+    let mut contacts = store.load_valid()?;
+    warn_about_duplicates(&contacts, &imported);
+    let count = imported.len();
+    for contact in imported {
+        contacts.add(contact);
+    }
+    store.save(&mut contacts)?;
+    eprintln!("{} contacts imported from {}.", count, path);
+    Ok(())
+}
+
+/// Report imported contacts with an e-mail address of a stored contact.
+fn warn_about_duplicates(contacts: &Contacts, imported: &[Contact]) {
+    for contact in imported.iter() {
+        for address in contact.emails.values() {
+            if let Some(other) = contacts.find_by_email(address) {
+                eprintln!(
+                    "Warning: {} of {} already belongs to {} ({}).",
+                    address,
+                    contact.name().unwrap_or_default(),
+                    other.name().unwrap_or_default(),
+                    other.id()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::contact::{ContactError, Contacts};
+use crate::email;
+use crate::phone::Tel;
+use crate::storage::{ContactStore, Query};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Version of the index format. It has to be increased whenever `Entry`
+/// changes.
+const FORMAT_VERSION: u32 = 2;
+
+/// Search index of contacts stored in JSON files. It is kept in the cache
+/// directory and rebuilt whenever any of the indexed files changes, see
+/// `load_matching()`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchIndex {
+    /// Fingerprint of the indexed files, see `stamp()`.
+    stamp: String,
+    /// Contacts in the order in which they are stored.
+    entries: Vec<Entry>,
+}
+
+/// Searchable properties of a contact.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entity_name: Option<String>,
+    /// Normalized e-mail addresses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    emails: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tels: Vec<Tel>,
+    /// Label values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
+    /// IDs of related contacts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    related: Vec<String>,
+}
+
+impl SearchIndex {
+    fn build(stamp: String, contacts: &Contacts) -> Self {
+        let entries = contacts
+            .contacts()
+            .iter()
+            .map(|contact| Entry {
+                id: contact.id().to_owned(),
+                full_name: contact.full_name().map(String::from),
+                entity_name: contact.entity_name().map(String::from),
+                emails: contact
+                    .emails
+                    .values()
+                    .map(|address| email::normalize(address))
+                    .collect(),
+                tels: contact.tels.values().cloned().collect(),
+                labels: contact.labels.values().cloned().collect(),
+                related: contact
+                    .relations()
+                    .iter()
+                    .map(|relation| relation.contact.clone())
+                    .collect(),
+            })
+            .collect();
+
+        Self { stamp, entries }
+    }
+
+    /// Load the index from a file. None is returned if the index does not
+    /// exist, cannot be read or does not have a given stamp.
+    fn load(path: &Path, stamp: &str) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let index: Self = serde_json::from_reader(BufReader::new(file)).ok()?;
+        Some(index).filter(|index| index.stamp == stamp)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Could not create cache directory.")?;
+        }
+        // Write to a temporary file first so that a concurrent search never
+        // reads a partially written index.
+        let tmp_path = path.with_extension("json.tmp");
+        let file = File::create(&tmp_path).context("Could not write search index.")?;
+        serde_json::to_writer(BufWriter::new(file), self)
+            .context("Could not write search index.")?;
+        fs::rename(&tmp_path, path).context("Could not write search index.")
+    }
+
+    /// Return positions of contacts which match a query together with
+    /// positions of contacts related to them, in the stored order.
+    fn matching(&self, query: &Query) -> Vec<usize> {
+        let positions: HashMap<&str, usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.id.as_str(), position))
+            .collect();
+
+        let mut selected = vec![false; self.entries.len()];
+        for (position, entry) in self.entries.iter().enumerate() {
+            if !query.matches(
+                entry.full_name.as_deref(),
+                entry.entity_name.as_deref(),
+                entry.tels.iter(),
+                entry.emails.iter(),
+                entry.labels.iter(),
+            ) {
+                continue;
+            }
+
+            selected[position] = true;
+            for id in entry.related.iter() {
+                if let Some(&related) = positions.get(id.as_str()) {
+                    selected[related] = true;
+                }
+            }
+        }
+
+        (0..selected.len())
+            .filter(|&position| selected[position])
+            .collect()
+    }
+}
+
+/// Load contacts which may match a query with help of the search index, see
+/// `ContactStore::load_matching()`. If the index is missing or outdated, all
+/// contacts are loaded and the index is rebuilt.
+///
+/// # Arguments
+///
+/// * `store` - storage of the contacts.
+/// * `files` - all files the contacts are loaded from. The index is
+///   outdated if any of them changes.
+/// * `load` - function which loads contacts at given positions (in the
+///   stored order) and all groups.
+pub fn load_matching<F>(
+    store: &dyn ContactStore,
+    files: &[PathBuf],
+    query: &Query,
+    load: F,
+) -> Result<(Contacts, Vec<ContactError>)>
+where
+    F: FnOnce(&[usize]) -> Result<(Contacts, Vec<ContactError>)>,
+{
+    if query.is_empty() {
+        return store.load();
+    }
+    load_indexed(&get_index_file()?, store, files, query, load)
+}
+
+/// See `load_matching()`. The index is kept in a given file.
+fn load_indexed<F>(
+    index_file: &Path,
+    store: &dyn ContactStore,
+    files: &[PathBuf],
+    query: &Query,
+    load: F,
+) -> Result<(Contacts, Vec<ContactError>)>
+where
+    F: FnOnce(&[usize]) -> Result<(Contacts, Vec<ContactError>)>,
+{
+    let stamp = stamp(FORMAT_VERSION, files);
+    if let Some(index) = SearchIndex::load(index_file, &stamp) {
+        return load(&index.matching(query));
+    }
+
+    let (contacts, errors) = store.load()?;
+    if let Err(error) = SearchIndex::build(stamp, &contacts).save(index_file) {
+        eprintln!("Warning: {:#}", error);
+    }
+    Ok((contacts, errors))
+}
+
+/// Remove the search index, e.g. when the indexed contacts must not be
+/// stored in plain text.
+pub fn remove() -> Result<()> {
+    let path = get_index_file()?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error).context("Could not remove search index."),
+    }
+}
+
+/// Fingerprint of files based on their paths, sizes and modification times.
+/// It includes a version of the index format, so that indices written by
+/// older versions are rebuilt.
+fn stamp(version: u32, files: &[PathBuf]) -> String {
+    let mut hasher = DefaultHasher::new();
+    version.hash(&mut hasher);
+    for path in files {
+        path.hash(&mut hasher);
+        if let Ok(metadata) = fs::metadata(path) {
+            metadata.len().hash(&mut hasher);
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+            modified.hash(&mut hasher);
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Get path to the search index in the standard cache directory.
+fn get_index_file() -> Result<PathBuf> {
+    let mut cache_path = match env::var("XDG_CACHE_HOME") {
+        Ok(val) => PathBuf::from(val),
+        Err(_) => {
+            let home = match env::var("HOME") {
+                Ok(home) => home,
+                Err(_) => bail!("Neither XDG_CACHE_HOME nor HOME environment variable set."),
+            };
+            let mut path = PathBuf::from(home);
+            path.push(".cache");
+            path
+        }
+    };
+
+    cache_path.push("conn-rs");
+    cache_path.push("search-index.json");
+    Ok(cache_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use regex::Regex;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::time::{Duration, SystemTime};
+
+    /// Temporary directory with an indexed file, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("con-rs-index-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("contacts.json"), "{}").unwrap();
+            Self(dir)
+        }
+
+        fn files(&self) -> Vec<PathBuf> {
+            vec![self.0.join("contacts.json")]
+        }
+
+        fn index_file(&self) -> PathBuf {
+            self.0.join("search-index.json")
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn store() -> MemoryStore {
+        let contact = |id: &str, full_name: &str| {
+            json!({
+                "id": id,
+                "fullName": full_name,
+                "entityName": null,
+                "tels": {},
+                "emails": {},
+                "labels": {},
+            })
+        };
+        MemoryStore::with_contacts(json!({
+            "contacts": [contact("john", "John Doe"), contact("jane", "Jane Roe")],
+        }))
+    }
+
+    /// Load contacts matching a name regex. Returns positions given to the
+    /// load function or None if all contacts have been loaded instead.
+    fn run(dir: &TempDir, store: &MemoryStore, name_regex: &str) -> Option<Vec<usize>> {
+        let regex = Regex::new(name_regex).unwrap();
+        let query = Query {
+            full_name_regex: Some(&regex),
+            ..Query::default()
+        };
+        let loaded = RefCell::new(None);
+        load_indexed(
+            &dir.index_file(),
+            store,
+            &dir.files(),
+            &query,
+            |positions| {
+                loaded.replace(Some(positions.to_vec()));
+                store.load()
+            },
+        )
+        .unwrap();
+        loaded.into_inner()
+    }
+
+    #[test]
+    fn stamp_changes_with_files() {
+        let dir = TempDir::new("stamp");
+        let files = dir.files();
+        let original = stamp(FORMAT_VERSION, &files);
+        assert_eq!(stamp(FORMAT_VERSION, &files), original);
+
+        fs::write(&files[0], "{ }").unwrap();
+        let resized = stamp(FORMAT_VERSION, &files);
+        assert_ne!(resized, original);
+
+        let file = File::options().write(true).open(&files[0]).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert_ne!(stamp(FORMAT_VERSION, &files), resized);
+    }
+
+    #[test]
+    fn stale_index_rebuilt() {
+        let dir = TempDir::new("stale");
+        let store = store();
+        assert_eq!(run(&dir, &store, "Jane"), None);
+        assert_eq!(run(&dir, &store, "Jane"), Some(vec![1]));
+
+        fs::write(&dir.files()[0], "{ }").unwrap();
+        assert_eq!(run(&dir, &store, "Jane"), None);
+        assert_eq!(run(&dir, &store, "Doe"), Some(vec![0]));
+    }
+
+    #[test]
+    fn format_version_mismatch() {
+        let dir = TempDir::new("version");
+        let contacts = store().load_valid().unwrap();
+        let stamp = |version| stamp(version, &dir.files());
+
+        SearchIndex::build(stamp(FORMAT_VERSION - 1), &contacts)
+            .save(&dir.index_file())
+            .unwrap();
+        assert!(SearchIndex::load(&dir.index_file(), &stamp(FORMAT_VERSION)).is_none());
+        assert_eq!(run(&dir, &store(), "Jane"), None);
+
+        let index = SearchIndex::load(&dir.index_file(), &stamp(FORMAT_VERSION)).unwrap();
+        assert_eq!(index.entries.len(), 2);
+    }
+}
@@ -1,8 +1,196 @@
-use crate::contact::Contacts;
-use anyhow::Result;
+use crate::config::Config;
+use crate::contact::{Contacts, CONTACTS_FILE};
+use crate::git;
+use crate::merge_driver::DRIVER_NAME;
+use crate::storage::{self, ContactStore, CONTACTS_DIR, GROUPS_FILE};
+use crate::vcard;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io;
 
-pub fn init() -> Result<()> {
-    Contacts::new().save_to_home()?;
+const GITATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// Initialize contacts storage.
+///
+/// # Arguments
+///
+/// * `store` - storage to be initialized.
+/// * `force` - overwrite existing contacts. Existing contacts are backed up
+///   before they are overwritten.
+/// * `from` - path to a contacts file (JSON) or vCard file used as initial
+///   contacts.
+/// * `init_git` - initialize a git repository in the directory with contacts
+///   and register the contacts merge driver.
+pub fn init(
+    store: &dyn ContactStore,
+    force: bool,
+    from: Option<&str>,
+    init_git: bool,
+) -> Result<()> {
+    let mut contacts = match from {
+        Some(path) => load_initial(path)?,
+        None => Contacts::new(),
+    };
+
+    if store.exists()? {
+        if !force {
+            bail!(
+                "Contacts already exist in {}. Use --force to overwrite them.",
+                Contacts::get_contacts_dir()?.display()
+            );
+        }
+
+        for backup in store.backup()? {
+            eprintln!("Existing contacts backed up to {}.", backup.display());
+        }
+    }
+
+    store.save(&mut contacts)?;
+    if init_git {
+        setup_git()?;
+    }
     eprintln!("Storage successfully initialized.");
     Ok(())
 }
+
+/// Load initial contacts from a contacts file (JSON) or a vCard file.
+fn load_initial(path: &str) -> Result<Contacts> {
+    let content = fs::read_to_string(path).with_context(|| format!("Could not read {}.", path))?;
+    if !content
+        .trim_start()
+        .get(..11)
+        .is_some_and(|start| start.eq_ignore_ascii_case("BEGIN:VCARD"))
+    {
+        return Contacts::load_from_path(path);
+    }
+
+    let country = Config::load()?.default_country()?;
+    let mut contacts = Contacts::new();
+    for (index, card) in vcard::parse(&content)?.iter().enumerate() {
+        let contact = vcard::to_contact(card, country)
+            .with_context(|| format!("Invalid vCard #{} in {}.", index, path))?;
+        if contacts.get(contact.id()).is_some() {
+            bail!("Duplicate vCard UID {} in {}.", contact.id(), path);
+        }
+        contacts.add(contact);
+    }
+    eprintln!(
+        "{} contacts imported from {}.",
+        contacts.contacts().len(),
+        path
+    );
+    Ok(contacts)
+}
+
+/// Initialize a git repository in the directory with contacts (if it is not
+/// already one), register the contacts merge driver and commit the contacts
+/// file.
+fn setup_git() -> Result<()> {
+    let contacts_dir = Contacts::get_contacts_dir()?;
+    if !contacts_dir.join(".git").exists() {
+        git::call(["init"])?;
+    }
+
+    let attributes_path = contacts_dir.join(GITATTRIBUTES_FILE);
+    let mut attributes = match fs::read_to_string(&attributes_path) {
+        Ok(attributes) => attributes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error).context("Could not read .gitattributes."),
+    };
+    let contacts_files = format!("{}/*.json", CONTACTS_DIR);
+    for pattern in [CONTACTS_FILE, &contacts_files, GROUPS_FILE] {
+        let attribute = format!("{} merge={}", pattern, DRIVER_NAME);
+        if !attributes.lines().any(|line| line.trim() == attribute) {
+            if !attributes.is_empty() && !attributes.ends_with('\n') {
+                attributes.push('\n');
+            }
+            attributes.push_str(&attribute);
+            attributes.push('\n');
+        }
+    }
+    fs::write(&attributes_path, attributes).context("Could not write .gitattributes.")?;
+
+    git::call([
+        "config",
+        &format!("merge.{}.name", DRIVER_NAME),
+        "con-rs contacts merge driver",
+    ])?;
+    git::call([
+        "config",
+        &format!("merge.{}.driver", DRIVER_NAME),
+        "con-rs merge-driver %O %A %B",
+    ])?;
+
+    let mut args = storage::with_pathspecs(&["add", "--all"]);
+    args.push(GITATTRIBUTES_FILE);
+    git::call(args)?;
+    if !git::succeeds(["diff", "--cached", "--quiet"])? {
+        git::call(["commit", "--message", "Initialize contacts"])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    fn names(store: &MemoryStore) -> Vec<String> {
+        store
+            .load_valid()
+            .unwrap()
+            .contacts()
+            .iter()
+            .filter_map(|contact| contact.name().map(String::from))
+            .collect()
+    }
+
+    #[test]
+    fn init_empty() {
+        let store = MemoryStore::default();
+        init(&store, false, None, false).unwrap();
+        assert!(store.exists().unwrap());
+        assert!(names(&store).is_empty());
+    }
+
+    #[test]
+    fn init_existing() {
+        let store = MemoryStore::with_contacts(json!({
+            "contacts": [{
+                "fullName": "John Doe",
+                "entityName": null,
+                "tels": {},
+                "emails": {},
+                "labels": {},
+            }],
+        }));
+        assert!(init(&store, false, None, false).is_err());
+        assert_eq!(names(&store), ["John Doe"]);
+
+        init(&store, true, None, false).unwrap();
+        assert!(names(&store).is_empty());
+    }
+
+    #[test]
+    fn init_from_file() {
+        let path = std::env::temp_dir().join(format!("con-rs-init-{}.json", std::process::id()));
+        let contacts = json!({
+            "contacts": [{
+                "fullName": null,
+                "entityName": "Acme",
+                "tels": {},
+                "emails": {},
+                "labels": {},
+            }],
+        });
+        fs::write(&path, contacts.to_string()).unwrap();
+
+        let store = MemoryStore::default();
+        let result = init(&store, false, path.to_str(), false);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(names(&store), ["Acme"]);
+    }
+}
@@ -0,0 +1,471 @@
+use crate::contact::{self, Contact};
+use crate::email;
+use crate::phone::Tel;
+use anyhow::{bail, Context, Result};
+use phonenumber::country;
+use std::str::Chars;
+use yaml_rust2::parser::Parser;
+use yaml_rust2::scanner::TScalarStyle;
+use yaml_rust2::Event;
+
+/// Components of a name in the order in which they form a full name.
+const NAME_FIELDS: [&str; 5] = ["prefix", "first name", "additional", "last name", "suffix"];
+
+/// A parsed YAML value, see `parse_documents()`.
+enum Node {
+    Scalar(String),
+    Map(Vec<(String, Node)>),
+    List(Vec<Node>),
+}
+
+impl Node {
+    /// Non-empty scalars of a scalar or a list.
+    fn strings(&self) -> Vec<&str> {
+        match self {
+            Node::Scalar(value) => Some(value.as_str())
+                .filter(|value| !value.is_empty())
+                .into_iter()
+                .collect(),
+            Node::List(items) => items.iter().flat_map(Node::strings).collect(),
+            Node::Map(_) => Vec::new(),
+        }
+    }
+}
+
+/// Parse contacts in khard YAML format, i.e. the format of templates used by
+/// `khard new` and `khard edit` and printed by `khard show --format yaml`.
+/// Multiple contacts can be separated by `---`.
+///
+/// Fields are mapped as follows: `Formatted name` (or name components) to
+/// full name, `Organisation` and `Title` to organization and title (a contact
+/// with `Kind` `organisation` or with an organisation only is an entity),
+/// `Phone` and `Email` to telephone numbers and e-mail addresses keyed by
+/// their types, `Categories` to tags, `Nickname`, `Note` and `Webpage` to
+/// labels `nick`, `note` and `url`, `Address` (`Post address` in older
+/// versions of khard) to labels `<type> address` and other fields (including
+/// `Private` ones) to labels of the same name. Invalid e-mail addresses are
+/// skipped with a warning.
+///
+/// # Arguments
+///
+/// * `content` - content of the file.
+/// * `country` - country used for telephone numbers without an international
+///   calling code.
+pub fn parse(content: &str, country: Option<country::Id>) -> Result<Vec<Contact>> {
+    let mut contacts = Vec::new();
+    for document in parse_documents(content)? {
+        let index = contacts.len() + 1;
+        let fields = match document {
+            Node::Map(fields) => fields,
+            // A document with comments only.
+            Node::Scalar(value) if value.is_empty() => continue,
+            _ => bail!("Contact #{} is not a mapping.", index),
+        };
+        let contact =
+            to_contact(&fields, country).with_context(|| format!("Invalid contact #{}.", index))?;
+        contacts.push(contact);
+    }
+    Ok(contacts)
+}
+
+fn to_contact(fields: &[(String, Node)], country: Option<country::Id>) -> Result<Contact> {
+    let find = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, node)| node)
+    };
+    let first = |name: &str| {
+        find(name)
+            .and_then(|node| node.strings().first().map(|value| value.to_string()))
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    let name = first("formatted name").or_else(|| {
+        let components: Vec<String> = NAME_FIELDS
+            .iter()
+            .filter_map(|field| first(field))
+            .collect();
+        Some(components.join(" ")).filter(|name| !name.is_empty())
+    });
+    let organization = first("organisation");
+    let is_entity = first("kind").is_some_and(|kind| {
+        ["org", "organisation", "organization"].contains(&kind.to_lowercase().as_str())
+    });
+
+    let mut contact = match (name, is_entity) {
+        (Some(name), false) => {
+            let mut contact = Contact::with_full_name(name);
+            contact.set_organization(organization)?;
+            contact.set_title(first("title"))?;
+            contact
+        }
+        (Some(name), true) => Contact::with_entity_name(name),
+        (None, _) => match organization {
+            Some(organization) => Contact::with_entity_name(organization),
+            None => bail!("Contact without a name."),
+        },
+    };
+
+    for (key, node) in fields.iter() {
+        match key.to_lowercase().as_str() {
+            "formatted name" | "organisation" | "title" | "kind" => (),
+            key if NAME_FIELDS.contains(&key) => (),
+            "phone" => {
+                for (kind, number) in typed_values(node, "tel") {
+                    let tel = Tel::parse(number.to_owned(), country);
+                    contact::insert_unique(&mut contact.tels, &kind, tel);
+                }
+            }
+            "email" => {
+                for (kind, address) in typed_values(node, "email") {
+                    if let Err(error) = email::validate(address) {
+                        eprintln!(
+                            "Warning: skipping e-mail address of {}. {:#}",
+                            contact.name().unwrap_or_default(),
+                            error
+                        );
+                        continue;
+                    }
+                    contact::insert_unique(&mut contact.emails, &kind, email::normalize(address));
+                }
+            }
+            "categories" => {
+                for category in node.strings() {
+                    for tag in category.split(',') {
+                        if !tag.trim().is_empty() {
+                            contact.tags.insert(tag.trim().to_owned());
+                        }
+                    }
+                }
+            }
+            "address" | "post address" => {
+                if let Node::Map(addresses) = node {
+                    for (kind, address) in addresses.iter() {
+                        let components: Vec<&str> = match address {
+                            Node::Map(components) => components
+                                .iter()
+                                .flat_map(|(_, component)| component.strings())
+                                .collect(),
+                            _ => address.strings(),
+                        };
+                        if !components.is_empty() {
+                            let key = format!("{} address", kind.to_lowercase());
+                            contact::insert_unique(
+                                &mut contact.labels,
+                                &key,
+                                components.join(", "),
+                            );
+                        }
+                    }
+                }
+            }
+            "private" => {
+                if let Node::Map(private) = node {
+                    for (key, value) in private.iter() {
+                        for value in value.strings() {
+                            contact::insert_unique(&mut contact.labels, key, value.to_owned());
+                        }
+                    }
+                }
+            }
+            key => {
+                let key = match key {
+                    "nickname" => "nick",
+                    "webpage" => "url",
+                    key => key,
+                };
+                for value in node.strings() {
+                    contact::insert_unique(&mut contact.labels, key, value.to_owned());
+                }
+            }
+        }
+    }
+
+    Ok(contact)
+}
+
+/// Values of a mapping from types to a value or a list of values. A value
+/// gets the first of its comma separated types which is not a generic one
+/// (e.g. `work` of `work, voice`) or a default key.
+fn typed_values<'a>(node: &'a Node, default: &str) -> Vec<(String, &'a str)> {
+    match node {
+        Node::Map(types) => types
+            .iter()
+            .flat_map(|(kind, values)| {
+                let key = kind
+                    .split(',')
+                    .map(|kind| kind.trim().to_lowercase())
+                    .find(|kind| {
+                        !kind.is_empty() && !["pref", "voice", "internet"].contains(&kind.as_str())
+                    })
+                    .unwrap_or_else(|| default.to_owned());
+                values
+                    .strings()
+                    .into_iter()
+                    .map(move |value| (key.clone(), value))
+            })
+            .collect(),
+        _ => node
+            .strings()
+            .into_iter()
+            .map(|value| (default.to_owned(), value))
+            .collect(),
+    }
+}
+
+/// Parse all documents of a YAML stream.
+fn parse_documents(content: &str) -> Result<Vec<Node>> {
+    let mut parser = Parser::new_from_str(content);
+    let mut documents = Vec::new();
+    loop {
+        match next_event(&mut parser)? {
+            Event::StreamEnd => return Ok(documents),
+            Event::StreamStart | Event::DocumentStart | Event::DocumentEnd => (),
+            event => documents.push(parse_node(&mut parser, event)?),
+        }
+    }
+}
+
+/// Parse a node starting with a given event.
+fn parse_node(parser: &mut Parser<Chars>, event: Event) -> Result<Node> {
+    match event {
+        Event::Scalar(value, style, _, _) => Ok(Node::Scalar(scalar(value, style))),
+        Event::SequenceStart(_, _) => {
+            let mut items = Vec::new();
+            loop {
+                match next_event(parser)? {
+                    Event::SequenceEnd => return Ok(Node::List(items)),
+                    event => items.push(parse_node(parser, event)?),
+                }
+            }
+        }
+        Event::MappingStart(_, _) => {
+            let mut fields = Vec::new();
+            loop {
+                let key = match next_event(parser)? {
+                    Event::MappingEnd => return Ok(Node::Map(fields)),
+                    Event::Scalar(key, _, _, _) => key,
+                    _ => bail!("Only scalar keys are supported."),
+                };
+                let event = next_event(parser)?;
+                fields.push((key, parse_node(parser, event)?));
+            }
+        }
+        Event::Alias(_) => bail!("YAML aliases are not supported."),
+        _ => bail!("Unexpected end of a YAML document."),
+    }
+}
+
+fn next_event(parser: &mut Parser<Chars>) -> Result<Event> {
+    let (event, _) = parser.next_token().context("Invalid YAML.")?;
+    Ok(event)
+}
+
+/// Value of a scalar as written, i.e. without type resolution, so that
+/// telephone numbers like `+420603123456` keep their form. Nulls are empty
+/// and trailing line breaks of block scalars are not kept.
+fn scalar(value: String, style: TScalarStyle) -> String {
+    match style {
+        TScalarStyle::Plain if ["~", "null", "Null", "NULL"].contains(&value.as_str()) => {
+            String::new()
+        }
+        TScalarStyle::Literal | TScalarStyle::Folded => value.trim_end().to_owned(),
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `khard show --format yaml` (comments shortened).
+    const CONTACT: &str = r#"# Every contact must contain a formatted name, it will be autofilled
+# from the full name below if not given.
+Formatted name : John Doe
+
+# Name components
+# every entry may contain a string or a list of strings
+Prefix     : 
+First name : John
+Additional : 
+Last name  : Doe
+Suffix     : 
+
+# Nickname
+# may contain a string or a list of strings
+Nickname : jd
+
+# Kind (individual/organisation/...)
+Kind : 
+
+# Organisation
+# format:
+#   Organisation :
+#       -
+#           - company
+#           - unit
+Organisation : 
+    - 
+        - Acme
+        - Sales
+
+# Organisation title and role
+Title : Engineer
+Role : 
+
+# phone numbers
+# format:
+#   Phone:
+#       type1, type2: number
+#       type3:
+#           - number1
+#           - number2
+Phone :
+    cell : +420603123456
+    work, voice : 
+        - +420222111000
+        - +420222111001
+
+# email addresses
+Email :
+    home : john@example.com
+    work : not-an-address
+
+# post addresses
+Address :
+    home :
+        Box      : 
+        Extended : 
+        Street   : Main Street 1
+        Code     : 11000
+        City     : Prague
+        Region   : 
+        Country  : Czechia
+
+# categories or tags
+Categories : [friends, 'golf, tennis']
+
+# web pages
+Webpage : https://example.com
+
+# private objects
+Private :
+    Jabber : jd@jabber.org
+
+# notes
+# for multi-line notes use:
+#   Note : |
+#       line one
+#       line two
+Note : |
+    Met at a conference.
+    Likes golf.
+"#;
+
+    fn parse_one(content: &str) -> Contact {
+        let mut contacts = parse(content, None).unwrap();
+        assert_eq!(contacts.len(), 1);
+        contacts.remove(0)
+    }
+
+    fn label<'a>(contact: &'a Contact, key: &str) -> Option<&'a str> {
+        contact.labels.get(key).map(String::as_str)
+    }
+
+    #[test]
+    fn khard_show_output() {
+        let contact = parse_one(CONTACT);
+        assert_eq!(contact.full_name(), Some("John Doe"));
+        assert_eq!(contact.organization(), Some("Acme"));
+        assert_eq!(contact.title(), Some("Engineer"));
+
+        let tels: Vec<(&str, &str)> = contact
+            .tels
+            .iter()
+            .map(|(key, tel)| (key.as_str(), tel.number()))
+            .collect();
+        assert_eq!(
+            tels,
+            [
+                ("cell", "+420603123456"),
+                ("work", "+420222111000"),
+                ("work 2", "+420222111001")
+            ]
+        );
+
+        let emails: Vec<(&str, &str)> = contact
+            .emails
+            .iter()
+            .map(|(key, address)| (key.as_str(), address.as_str()))
+            .collect();
+        assert_eq!(emails, [("home", "john@example.com")]);
+
+        let tags: Vec<&str> = contact.tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, ["friends", "golf", "tennis"]);
+
+        assert_eq!(label(&contact, "nick"), Some("jd"));
+        assert_eq!(label(&contact, "url"), Some("https://example.com"));
+        assert_eq!(label(&contact, "jabber"), None);
+        assert_eq!(label(&contact, "Jabber"), Some("jd@jabber.org"));
+        assert_eq!(
+            label(&contact, "home address"),
+            Some("Main Street 1, 11000, Prague, Czechia")
+        );
+        assert_eq!(
+            label(&contact, "note"),
+            Some("Met at a conference.\nLikes golf.")
+        );
+        assert_eq!(label(&contact, "role"), None);
+    }
+
+    #[test]
+    fn multiple_documents() {
+        let content = "Formatted name : John Doe\n---\n# comment\n---\nOrganisation : Acme\n";
+        let contacts = parse(content, None).unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[1].entity_name(), Some("Acme"));
+    }
+
+    #[test]
+    fn name_components() {
+        let contact = parse_one("First name : John\nLast name : \"Doe\"\nKind : individual\n");
+        assert_eq!(contact.full_name(), Some("John Doe"));
+    }
+
+    #[test]
+    fn flow_sequence() {
+        let contact =
+            parse_one("Formatted name : John Doe\nNickname : [\"a, b\", c , 'd'] # comment\n");
+        assert_eq!(label(&contact, "nick"), Some("a, b"));
+        assert_eq!(label(&contact, "nick 2"), Some("c"));
+        assert_eq!(label(&contact, "nick 3"), Some("d"));
+
+        assert!(parse("Formatted name : John Doe\nNickname : [a, b\n", None).is_err());
+        assert!(parse("Formatted name : John Doe\nNickname : [a] b\n", None).is_err());
+    }
+
+    #[test]
+    fn quoted_scalars() {
+        let content = r#"Formatted name : 'John ''JD'' Doe'
+Note : "a\"b\nc"
+Webpage : plain # comment
+Phone : +420603123456
+Kind : ~
+"#;
+        let contact = parse_one(content);
+        assert_eq!(contact.full_name(), Some("John 'JD' Doe"));
+        assert_eq!(label(&contact, "note"), Some("a\"b\nc"));
+        assert_eq!(label(&contact, "url"), Some("plain"));
+        assert_eq!(contact.tels["tel"].number(), "+420603123456");
+
+        assert!(parse("Formatted name : 'open\n", None).is_err());
+    }
+
+    #[test]
+    fn invalid_documents() {
+        assert!(parse("- a\n- b\n", None).is_err());
+        assert!(parse("Note : x\n  Title : y\n", None).is_err());
+        assert!(parse("Note : x\n", None).is_err());
+    }
+}
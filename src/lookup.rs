@@ -0,0 +1,40 @@
+use crate::email;
+use crate::storage::ContactStore;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+/// Print e-mail addresses of all contacts whose name matches a regular
+/// expression, one address per line.
+///
+/// # Arguments
+///
+/// * `name_regex` - a regular expression matched against full names and
+///   entity names.
+/// * `all` - print all e-mail addresses ordered by preference instead of
+///   the preferred address only.
+pub fn email(store: &dyn ContactStore, name_regex: &str, all: bool) -> Result<()> {
+    let regex = Regex::new(name_regex).context("Invalid name regex.")?;
+    let contacts = store.load_valid()?;
+
+    let mut found = false;
+    for contact in contacts.contacts() {
+        if !contact.name().is_some_and(|name| regex.is_match(name)) {
+            continue;
+        }
+
+        if all {
+            for address in contact.emails.values() {
+                println!("{}", email::normalize(address));
+                found = true;
+            }
+        } else if let Some(address) = contact.preferred_email() {
+            println!("{}", email::normalize(address));
+            found = true;
+        }
+    }
+
+    if !found {
+        bail!("No e-mail address found.");
+    }
+    Ok(())
+}
@@ -1,29 +1,157 @@
 use anyhow::{bail, Context, Result};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
+mod abook;
 mod actions;
 mod add;
+mod config;
 mod contact;
+mod convert;
+mod delete;
+mod doctor;
 mod edit;
+mod email;
+mod encryption;
 mod git;
+mod group;
+mod history;
+mod import;
+mod index;
 mod init;
+mod khard;
+mod lookup;
+#[cfg(test)]
+mod memory;
+mod merge;
+mod merge_driver;
 mod mutt;
+mod phone;
 mod print;
+mod recent;
+mod relation;
 mod search;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+mod sync;
+mod tags;
+mod threeway;
+mod vcard;
+mod vdir;
 
 fn main() -> Result<()> {
     let add_cmd = SubCommand::with_name("add").about("Add a new contact.");
-    let init_cmd = SubCommand::with_name("init").about("(Re-)initialize contacts storage.");
+    let init_cmd = SubCommand::with_name("init")
+        .about("Initialize contacts storage.")
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Overwrite existing contacts. The contacts file is backed up first."),
+        )
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .value_name("FILE")
+                .help("Initialize contacts from a contacts (JSON) or vCard file."),
+        )
+        .arg(Arg::with_name("git").long("git").help(
+            "Initialize a git repository in the directory with contacts and \
+             register the contacts merge driver.",
+        ));
+    let merge_driver_cmd = SubCommand::with_name("merge-driver")
+        .about("Merge three versions of a contacts file. This is meant to be called by git.")
+        .arg(
+            Arg::with_name("base")
+                .help("Path to the common ancestor (%O).")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("ours")
+                .help("Path to our version (%A). The result is written to this file.")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("theirs")
+                .help("Path to their version (%B).")
+                .required(true),
+        );
+    let doctor_cmd = SubCommand::with_name("doctor")
+        .alias("lint")
+        .about("Check all contacts and report problems found.")
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print found problems as a JSON array."),
+        )
+        .arg(Arg::with_name("fix").long("fix").help(
+            "Fix problems which can be fixed safely, for example \
+                     surrounding whitespace or telephone numbers not stored \
+                     in normalized (E.164) form.",
+        ));
 
     let search_print_cmd = SubCommand::with_name("print").about("Pretty print search matches.");
     let search_edit_cmd = SubCommand::with_name("edit").about(
         "Edit a contact. This command fails if no contact or more than one \
          contact is matched.",
     );
-    let search_mutt_cmd = SubCommand::with_name("mutt").about(
-        "Search contacts and print matches in Mutt compatible format. \
-         This command could be used by `query_command` in Mutt.",
+    let search_mutt_cmd = SubCommand::with_name("mutt")
+        .about(
+            "Search contacts and print matches in Mutt compatible format. \
+             This command could be used by `query_command` in Mutt, e.g. \
+             `con-rs search mutt %s`.",
+        )
+        .arg(Arg::with_name("query").help(
+            "Print only contacts whose name, e-mail address or label contains \
+             the query (case insensitive).",
+        ))
+        .arg(
+            Arg::with_name("neomutt")
+                .long("neomutt")
+                .conflicts_with("aerc")
+                .help("Add tags of contacts to the output for `query_format` of NeoMutt."),
+        )
+        .arg(Arg::with_name("aerc").long("aerc").help(
+            "Print matches in the format of `address-book-cmd` of aerc, e.g. \
+             `con-rs search mutt --aerc %s`.",
+        ));
+    let search_mutt_aliases_cmd = SubCommand::with_name("mutt-aliases")
+        .about(
+            "Print Mutt aliases (alias <nick> Name <address>) of all e-mail \
+             addresses of matched contacts, e.g. to create a file for \
+             `alias_file` in Mutt.",
+        )
+        .arg(
+            Arg::with_name("nick-label")
+                .long("nick-label")
+                .value_name("label")
+                .default_value("nick")
+                .help(
+                    "Label with the nick of a contact. Nicks of contacts \
+                     without the label are made of initials of their names.",
+                ),
+        );
+    let search_delete_cmd = SubCommand::with_name("delete").about(
+        "Delete all matched contacts. Relations to the deleted contacts are \
+         removed.",
+    );
+    let search_merge_cmd = SubCommand::with_name("merge").about(
+        "Merge all matched contacts into the first matched contact. \
+         Relations to the merged contacts are redirected to the resulting \
+         contact.",
     );
+    let search_history_cmd = SubCommand::with_name("history")
+        .about(
+            "Print committed changes of a contact. The contacts directory \
+             must be a git repository. This command fails if no contact or \
+             more than one contact is matched.",
+        )
+        .arg(
+            Arg::with_name("revert")
+                .long("revert")
+                .help("Restore the contact to its version from a git revision.")
+                .value_name("commit")
+                .takes_value(true),
+        );
 
     let search_cmd = SubCommand::with_name("search")
         .about(
@@ -35,6 +163,10 @@ fn main() -> Result<()> {
         .subcommand(search_print_cmd)
         .subcommand(search_edit_cmd)
         .subcommand(search_mutt_cmd)
+        .subcommand(search_mutt_aliases_cmd)
+        .subcommand(search_delete_cmd)
+        .subcommand(search_merge_cmd)
+        .subcommand(search_history_cmd)
         .arg(
             Arg::with_name("full-name")
                 .long("full-name")
@@ -70,6 +202,224 @@ fn main() -> Result<()> {
                      search output.",
                 )
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("related-to")
+                .long("related-to")
+                .help(
+                    "A regular expression. Only contacts related to a contact \
+                     whose full name or entity name matches the regex are \
+                     included in the search output.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("group")
+                .long("group")
+                .short("g")
+                .help(
+                    "Name of a group. Only members of the group (including \
+                     members of nested groups) are included in the search \
+                     output. Use with `mutt` to expand the group to e-mail \
+                     addresses of all members.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .help(
+                    "A tag. Contacts without the tag won't be included in the \
+                     search output. Can be given multiple times, see \
+                     --any-tag.",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("any-tag")
+                .long("any-tag")
+                .requires("tag")
+                .help(
+                    "Include contacts with at least one of the tags given by \
+                     --tag instead of contacts with all of the tags.",
+                ),
+        )
+        .arg(
+            Arg::with_name("modified-since")
+                .long("modified-since")
+                .help(
+                    "Date (YYYY-MM-DD) or time (RFC 3339). Contacts modified \
+                     before the time won't be included in the search output.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("created-before")
+                .long("created-before")
+                .help(
+                    "Date (YYYY-MM-DD) or time (RFC 3339). Contacts created \
+                     at or after the time won't be included in the search \
+                     output.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .help(
+                    "Sort search results by full name or entity name (name), \
+                     last word of full name (surname), entity name and \
+                     organization (entity), time of the last modification \
+                     (modified) or time of creation (created). Search \
+                     results are in the order of the contacts file if not \
+                     given.",
+                )
+                .possible_values(&["name", "surname", "entity", "modified", "created"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .long("reverse")
+                .requires("sort")
+                .help("Sort search results in descending order."),
+        )
+        .arg(
+            Arg::with_name("offset")
+                .long("offset")
+                .help("Skip the given number of search results.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .help("Maximum number of search results.")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("lenient").long("lenient").help(
+            "Skip contacts which violate contact rules (e.g. both full name \
+             and entity name are set) instead of failing. Use `doctor` \
+             command to find such contacts.",
+        ))
+        .arg(
+            Arg::with_name("tel")
+                .long("tel")
+                .short("t")
+                .help(
+                    "A telephone number. Contacts without a matching \
+                     telephone number won't be included in the search \
+                     output. Numbers are compared in their normalized form.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("email")
+                .long("email")
+                .help(
+                    "An e-mail address. Contacts without the address won't be \
+                     included in the search output. Addresses are compared in \
+                     their normalized form.",
+                )
+                .takes_value(true),
+        );
+
+    let relation_args = [
+        Arg::with_name("from")
+            .help("A regular expression matching name of exactly one contact.")
+            .required(true),
+        Arg::with_name("type")
+            .help("Type of the relation, e.g. spouse or works-at.")
+            .required(true),
+        Arg::with_name("to")
+            .help("A regular expression matching name of exactly one related contact.")
+            .required(true),
+    ];
+    let relation_cmd = SubCommand::with_name("relation")
+        .about("Manage relations between contacts.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Add a relation between two contacts.")
+                .args(&relation_args),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Remove a relation between two contacts.")
+                .args(&relation_args),
+        );
+
+    let group_member_args = [
+        Arg::with_name("group")
+            .help("Name of the group.")
+            .required(true),
+        Arg::with_name("name")
+            .help(
+                "A regular expression. All contacts whose full name or entity \
+                 name matches the regex are affected.",
+            )
+            .required_unless("nested"),
+        Arg::with_name("nested")
+            .long("nested")
+            .help("Name of a nested group.")
+            .takes_value(true),
+    ];
+    let group_cmd = SubCommand::with_name("group")
+        .about("Manage groups of contacts.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("create")
+                .about("Create a new empty group.")
+                .arg(
+                    Arg::with_name("group")
+                        .help("Name of the group.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Add contacts or a nested group to a group.")
+                .args(&group_member_args),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Remove contacts or a nested group from a group.")
+                .args(&group_member_args),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List all groups or members of a group.")
+                .arg(Arg::with_name("group").help("Name of the group.")),
+        );
+
+    let tags_cmd = SubCommand::with_name("tags")
+        .about("List all tags together with number of tagged contacts.");
+
+    let email_cmd = SubCommand::with_name("email")
+        .about("Print preferred e-mail address of matching contacts.")
+        .arg(
+            Arg::with_name("name")
+                .help(
+                    "A regular expression matched against full names and \
+                     entity names.",
+                )
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .short("a")
+                .help("Print all e-mail addresses, the preferred ones first."),
+        );
+
+    let recent_cmd = SubCommand::with_name("recent")
+        .about("List recently created or modified contacts.")
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .short("l")
+                .help("Maximum number of listed contacts.")
+                .default_value("10"),
         );
 
     let git_cmd = SubCommand::with_name("git")
@@ -81,6 +431,66 @@ fn main() -> Result<()> {
                 .multiple(true),
         );
 
+    let convert_storage_cmd = SubCommand::with_name("convert-storage")
+        .about("Move contacts to another storage and configure it.")
+        .arg(
+            Arg::with_name("storage")
+                .help(
+                    "New storage: a single file, a file per contact, a \
+                     directory of vCard files (vdir) or an SQLite database.",
+                )
+                .possible_values(&["file", "directory", "vdir", "sqlite"])
+                .required(true),
+        );
+
+    let export_cmd = SubCommand::with_name("export")
+        .about(
+            "Export all contacts to a contacts file (JSON). Use `init --from` \
+             to import a contacts file.",
+        )
+        .arg(
+            Arg::with_name("file")
+                .help("Path of the contacts file.")
+                .required(true),
+        );
+
+    let import_cmd = SubCommand::with_name("import")
+        .about(
+            "Import contacts from an address book of another application and \
+             add them to the stored contacts.",
+        )
+        .arg(
+            Arg::with_name("format")
+                .help(
+                    "Format of the file: abook address book (e.g. \
+                     ~/.abook/addressbook) or khard YAML contact template.",
+                )
+                .possible_values(&["abook", "khard-yaml"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("file")
+                .help("Path of the file.")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Print contacts which would be imported without storing them."),
+        );
+
+    let encrypt_cmd = SubCommand::with_name("encrypt").about(
+        "Encrypt the contacts file with the configured key file or a passphrase. \
+         Other commands decrypt and encrypt it transparently.",
+    );
+    let decrypt_cmd =
+        SubCommand::with_name("decrypt").about("Store the contacts file unencrypted again.");
+
+    let sync_cmd = SubCommand::with_name("sync").about(
+        "Commit local changes, merge remote changes contact by contact and push \
+         the result to the upstream git branch.",
+    );
+
     let matches = App::new("con-rs")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Martin Indra <martin.indra@mgn.cz>")
@@ -91,14 +501,72 @@ fn main() -> Result<()> {
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(add_cmd)
         .subcommand(init_cmd)
+        .subcommand(doctor_cmd)
         .subcommand(search_cmd)
+        .subcommand(relation_cmd)
+        .subcommand(group_cmd)
+        .subcommand(tags_cmd)
+        .subcommand(email_cmd)
+        .subcommand(recent_cmd)
+        .subcommand(convert_storage_cmd)
+        .subcommand(export_cmd)
+        .subcommand(import_cmd)
+        .subcommand(encrypt_cmd)
+        .subcommand(decrypt_cmd)
+        .subcommand(sync_cmd)
+        .subcommand(merge_driver_cmd)
         .subcommand(git_cmd)
         .get_matches();
 
     match matches.subcommand() {
-        ("add", _) => add::add_contact(),
-        ("init", _) => init::init(),
+        ("add", _) => add::add_contact(storage::open()?.as_ref()),
+        ("init", Some(matches)) => init::init(
+            storage::open()?.as_ref(),
+            matches.is_present("force"),
+            matches.value_of("from"),
+            matches.is_present("git"),
+        ),
+        ("doctor", Some(matches)) => doctor::doctor(
+            storage::open()?.as_ref(),
+            matches.is_present("json"),
+            matches.is_present("fix"),
+        ),
         ("search", Some(matches)) => handle_search(matches),
+        ("relation", Some(matches)) => handle_relation(matches),
+        ("group", Some(matches)) => handle_group(matches),
+        ("tags", _) => tags::list(storage::open()?.as_ref()),
+        ("recent", Some(matches)) => {
+            let limit = matches.value_of("limit").unwrap();
+            recent::recent(
+                storage::open()?.as_ref(),
+                limit.parse().context("Invalid limit.")?,
+            )
+        }
+        ("email", Some(matches)) => lookup::email(
+            storage::open()?.as_ref(),
+            matches.value_of("name").unwrap(),
+            matches.is_present("all"),
+        ),
+        ("convert-storage", Some(matches)) => {
+            convert::convert_storage(matches.value_of("storage").unwrap())
+        }
+        ("export", Some(matches)) => {
+            convert::export(storage::open()?.as_ref(), matches.value_of("file").unwrap())
+        }
+        ("import", Some(matches)) => import::import(
+            storage::open()?.as_ref(),
+            matches.value_of("format").unwrap(),
+            matches.value_of("file").unwrap(),
+            matches.is_present("dry-run"),
+        ),
+        ("encrypt", _) => encryption::encrypt_contacts(),
+        ("decrypt", _) => encryption::decrypt_contacts(),
+        ("sync", _) => sync::sync(),
+        ("merge-driver", Some(matches)) => merge_driver::merge_driver(
+            matches.value_of("base").unwrap(),
+            matches.value_of("ours").unwrap(),
+            matches.value_of("theirs").unwrap(),
+        ),
         ("git", Some(matches)) => handle_git(matches),
         _ => panic!("Unrecognized command"),
     }
@@ -124,16 +592,122 @@ fn handle_search(matches: &ArgMatches) -> Result<()> {
             .set_entity_name_regex(name_regex)
             .context("Invalid name regex.")?;
     }
+    if let Some(related_to_regex) = matches.value_of("related-to") {
+        options
+            .set_related_to_regex(related_to_regex)
+            .context("Invalid related-to regex.")?;
+    }
+    if let Some(group) = matches.value_of("group") {
+        options.set_group(group);
+    }
+    if let Some(tags) = matches.values_of("tag") {
+        options.set_tags(
+            tags.map(String::from).collect(),
+            matches.is_present("any-tag"),
+        );
+    }
+    if let Some(modified_since) = matches.value_of("modified-since") {
+        options.set_modified_since(search::parse_time(modified_since)?);
+    }
+    if let Some(created_before) = matches.value_of("created-before") {
+        options.set_created_before(search::parse_time(created_before)?);
+    }
+    if let Some(sort) = matches.value_of("sort") {
+        options.set_sort(sort.parse()?, matches.is_present("reverse"));
+    }
+    let offset = match matches.value_of("offset") {
+        Some(offset) => offset.parse().context("Invalid offset.")?,
+        None => 0,
+    };
+    let limit = match matches.value_of("limit") {
+        Some(limit) => Some(limit.parse().context("Invalid limit.")?),
+        None => None,
+    };
+    options.set_page(offset, limit);
+    options.set_lenient(matches.is_present("lenient"));
+    if let Some(tel) = matches.value_of("tel") {
+        let country = config::Config::load()?.default_country()?;
+        options.set_tel(tel, country);
+    }
+    if let Some(address) = matches.value_of("email") {
+        options.set_email(address);
+    }
 
     let action_subcommand = matches.subcommand();
     let action: Box<dyn actions::MatchAction> = match action_subcommand {
         ("print", _) => Box::new(print::PrintExporter::new()),
-        ("mutt", _) => Box::new(mutt::Mutt::new()),
+        ("mutt", Some(matches)) => {
+            if let Some(query) = matches.value_of("query") {
+                options.set_text(query);
+            }
+            options.set_require_email(true);
+            let format = if matches.is_present("neomutt") {
+                mutt::QueryFormat::Neomutt
+            } else if matches.is_present("aerc") {
+                mutt::QueryFormat::Aerc
+            } else {
+                mutt::QueryFormat::Mutt
+            };
+            Box::new(mutt::Mutt::new(format))
+        }
+        ("mutt-aliases", Some(matches)) => {
+            options.set_require_email(true);
+            Box::new(mutt::MuttAliases::new(
+                matches.value_of("nick-label").unwrap().to_owned(),
+            ))
+        }
         ("edit", _) => Box::new(edit::EditContact::new()),
+        ("delete", _) => Box::new(delete::DeleteContacts::new()),
+        ("merge", _) => Box::new(merge::MergeContacts::new()),
+        ("history", Some(matches)) => Box::new(history::History::new(
+            matches.value_of("revert").map(String::from),
+        )?),
         _ => bail!("Invalid export method."),
     };
 
-    search::search(options, action)
+    search::search(storage::open()?.as_ref(), options, action)
+}
+
+fn handle_relation(matches: &ArgMatches) -> Result<()> {
+    let store = storage::open()?;
+    match matches.subcommand() {
+        ("add", Some(matches)) => relation::add(
+            store.as_ref(),
+            matches.value_of("from").unwrap(),
+            matches.value_of("type").unwrap(),
+            matches.value_of("to").unwrap(),
+        ),
+        ("remove", Some(matches)) => relation::remove(
+            store.as_ref(),
+            matches.value_of("from").unwrap(),
+            matches.value_of("type").unwrap(),
+            matches.value_of("to").unwrap(),
+        ),
+        _ => bail!("Invalid relation command."),
+    }
+}
+
+fn handle_group(matches: &ArgMatches) -> Result<()> {
+    let store = storage::open()?;
+    match matches.subcommand() {
+        ("create", Some(matches)) => {
+            group::create(store.as_ref(), matches.value_of("group").unwrap())
+        }
+        ("add", Some(matches)) => group::add(
+            store.as_ref(),
+            matches.value_of("group").unwrap(),
+            matches.value_of("name"),
+            matches.value_of("nested"),
+        ),
+        ("remove", Some(matches)) => group::remove(
+            store.as_ref(),
+            matches.value_of("group").unwrap(),
+            matches.value_of("name"),
+            matches.value_of("nested"),
+        ),
+        ("list", Some(matches)) => group::list(store.as_ref(), matches.value_of("group")),
+        _ => bail!("Invalid group command."),
+    }
 }
 
 fn handle_git(matches: &ArgMatches) -> Result<()> {
@@ -0,0 +1,60 @@
+use crate::contact::{Contact, ContactError, Contacts, Group};
+use crate::storage::ContactStore;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+/// Contacts kept in memory, e.g. to test commands without touching the
+/// directory with contacts. Contacts are kept serialized, so that loaded
+/// contacts never share state with saved ones.
+#[derive(Default)]
+pub struct MemoryStore {
+    stored: RefCell<Option<Value>>,
+}
+
+/// Serialized contacts, see `MemoryStore::load()`.
+#[derive(Deserialize)]
+struct Stored {
+    contacts: Vec<Contact>,
+    #[serde(default)]
+    groups: Vec<Group>,
+}
+
+impl MemoryStore {
+    /// Create a store with initial contacts, i.e. an initialized store.
+    pub fn with_contacts(contacts: Value) -> Self {
+        Self {
+            stored: RefCell::new(Some(contacts)),
+        }
+    }
+}
+
+impl ContactStore for MemoryStore {
+    fn exists(&self) -> Result<bool> {
+        Ok(self.stored.borrow().is_some())
+    }
+
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)> {
+        let stored = match self.stored.borrow().as_ref() {
+            Some(stored) => Stored::deserialize(stored)?,
+            None => bail!("Could not load contacts. Make sure contacts are initialized."),
+        };
+        Ok(Contacts::from_parts(stored.contacts, stored.groups))
+    }
+
+    fn save(&self, contacts: &mut Contacts) -> Result<()> {
+        self.stored.replace(Some(serde_json::to_value(&*contacts)?));
+        Ok(())
+    }
+
+    fn backup(&self) -> Result<Vec<PathBuf>> {
+        self.stored.replace(None);
+        Ok(Vec::new())
+    }
+
+    fn contact_path(&self, _id: &str) -> Result<String> {
+        bail!("Contacts in memory are not stored in files.");
+    }
+}
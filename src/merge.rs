@@ -0,0 +1,41 @@
+use crate::actions::MatchAction;
+use crate::contact::Contact;
+use anyhow::{bail, Result};
+use dialoguer::Confirm;
+
+pub struct MergeContacts {}
+
+impl MergeContacts {
+    pub fn new() -> Self {
+        MergeContacts {}
+    }
+}
+
+impl MatchAction for MergeContacts {
+    fn process(&self, mut contacts: Vec<&mut Contact>) -> Result<bool> {
+        if contacts.len() < 2 {
+            bail!("At least two contacts must be matched.");
+        }
+
+        let mut others = contacts.split_off(1);
+        let target = contacts.pop().unwrap();
+
+        for other in others.iter_mut() {
+            target.merge(other);
+        }
+
+        eprintln!("--------------------------------------------------");
+        eprint!("{}", target);
+        eprintln!("--------------------------------------------------");
+
+        let confirmed = Confirm::new()
+            .with_prompt(format!(
+                "Merge {} contacts into the above contact?",
+                others.len() + 1
+            ))
+            .default(false)
+            .interact()?;
+
+        Ok(confirmed)
+    }
+}
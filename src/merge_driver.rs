@@ -0,0 +1,75 @@
+use crate::encryption;
+use crate::storage;
+use crate::threeway;
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fs;
+
+/// Name of the merge driver in git configuration and `.gitattributes`.
+pub const DRIVER_NAME: &str = "con-rs";
+
+/// Merge three versions of a stored file, see `threeway::merge_file()`. This
+/// is meant to be called by git as a merge driver: the result is written to
+/// `ours` and an error is returned if some changes could not be merged
+/// automatically. Our version is kept for such changes. The result is
+/// encrypted if our or their version is encrypted.
+///
+/// # Arguments
+///
+/// * `base` - path to the common ancestor.
+/// * `ours` - path to our version.
+/// * `theirs` - path to their version.
+pub fn merge_driver(base: &str, ours: &str, theirs: &str) -> Result<()> {
+    let (base_version, _) = load(base)?;
+    let (our_version, ours_encrypted) = load(ours)?;
+    let (their_version, theirs_encrypted) = load(theirs)?;
+
+    let mut conflicts = Vec::new();
+    let merged = threeway::merge_file(
+        base_version.as_ref(),
+        our_version.as_ref(),
+        their_version.as_ref(),
+        &mut |conflict| {
+            conflicts.push(conflict.path.clone());
+            Ok(conflict.ours.cloned())
+        },
+    )?;
+    save(ours, merged, ours_encrypted || theirs_encrypted)?;
+
+    if !conflicts.is_empty() {
+        for path in conflicts.iter() {
+            eprintln!("Conflicting changes of {}, local version kept.", path);
+        }
+        bail!(
+            "{} conflicting changes have to be resolved manually.",
+            conflicts.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Load a stored file and decrypt it if needed. None is returned for an
+/// empty file (e.g. a missing common ancestor) and true if the file is
+/// encrypted.
+fn load(path: &str) -> Result<(Option<Value>, bool)> {
+    let content = fs::read_to_string(path).with_context(|| format!("Could not read {}.", path))?;
+    if content.trim().is_empty() {
+        return Ok((None, false));
+    }
+    let encrypted = encryption::is_encrypted(&content);
+    let content = encryption::decrypt_if_encrypted(content)?;
+    let value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid contacts file {}.", path))?;
+    Ok((Some(value), encrypted))
+}
+
+/// Save a merged file in the standard format of its kind.
+fn save(path: &str, merged: Option<Value>, encrypted: bool) -> Result<()> {
+    let content = match merged {
+        Some(merged) if encrypted => encryption::encrypt(&storage::format_file(merged)?)?,
+        Some(merged) => storage::format_file(merged)?,
+        None => String::new(),
+    };
+    fs::write(path, content).with_context(|| format!("Could not write {}.", path))
+}
@@ -1,34 +1,285 @@
 use crate::actions::MatchAction;
 use crate::contact::Contact;
-use anyhow::{bail, Result};
+use crate::email;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
 
-pub struct Mutt {}
+/// Output format of `Mutt`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum QueryFormat {
+    /// Mutt `query_command` protocol: a header line followed by lines with
+    /// tab separated address, name and other information (the name of the
+    /// address).
+    Mutt,
+    /// The same as `Mutt` but the other information contains tags of the
+    /// contact too, they can be shown by `%e` in NeoMutt `query_format`.
+    Neomutt,
+    /// aerc `address-book-cmd` protocol: lines with tab separated address and
+    /// name without any header.
+    Aerc,
+}
+
+/// Print e-mail addresses of matches for `query_command` of Mutt or a
+/// similar command of related clients, see `QueryFormat`. The command always
+/// succeeds, no matches are reported in the header line.
+pub struct Mutt {
+    format: QueryFormat,
+}
 
 impl Mutt {
-    pub fn new() -> Self {
-        Mutt {}
+    pub fn new(format: QueryFormat) -> Self {
+        Self { format }
     }
 }
 
 impl MatchAction for Mutt {
     fn process(&self, contacts: Vec<&mut Contact>) -> Result<bool> {
-        if contacts.is_empty() {
-            bail!("No contact has been matched.");
+        if self.format != QueryFormat::Aerc {
+            match contacts.len() {
+                0 => println!("No contacts found."),
+                1 => println!("One contact found."),
+                count => println!("{} contacts found.", count),
+            }
         }
 
-        println!("{} contacts found.", contacts.len());
-
         for contact in contacts {
+            let name = field(contact.name().unwrap_or_default());
             for (email_name, email_address) in contact.emails.iter() {
-                println!(
-                    "{}\t{}\t({})",
-                    email_address,
-                    contact.full_name().or(contact.entity_name()).unwrap(),
-                    email_name
-                );
+                let address = field(&email::normalize(email_address));
+                match self.format {
+                    QueryFormat::Mutt => {
+                        println!("{}\t{}\t({})", address, name, field(email_name))
+                    }
+                    QueryFormat::Neomutt => {
+                        let mut other = format!("({})", field(email_name));
+                        for tag in contact.tags.iter() {
+                            other.push(' ');
+                            other.push_str(&field(tag));
+                        }
+                        println!("{}\t{}\t{}", address, name, other)
+                    }
+                    QueryFormat::Aerc => println!("{}\t{}", address, name),
+                }
             }
         }
 
         Ok(false)
     }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+/// Replace characters which would break the tab separated output.
+fn field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Print matches as Mutt aliases (`alias <nick> Name <address>`), e.g. to be
+/// stored in a file sourced by Mutt. The nick of a contact is taken from one
+/// of its labels or generated from initials of its name. The first e-mail
+/// address of a contact gets the nick itself, other addresses get the nick
+/// suffixed with the name of the address (e.g. `jd-work`). Aliases shared
+/// by more than one address are suffixed with the beginning of the ID of
+/// their contact (e.g. `jd-3f2a9c1e`), so aliases do not depend on the
+/// order of matches.
+pub struct MuttAliases {
+    nick_label: String,
+}
+
+impl MuttAliases {
+    /// # Arguments
+    ///
+    /// * `nick_label` - name of the label with a nick of a contact.
+    pub fn new(nick_label: String) -> Self {
+        Self { nick_label }
+    }
+
+    fn nick(&self, contact: &Contact) -> String {
+        if let Some(nick) = contact.labels.get(&self.nick_label) {
+            let nick = sanitize_nick(nick);
+            if !nick.is_empty() {
+                return nick;
+            }
+        }
+
+        let words: Vec<&str> = contact
+            .name()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        let nick = match words.as_slice() {
+            [word] => sanitize_nick(word),
+            words => words
+                .iter()
+                .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+                .collect(),
+        };
+        if !nick.is_empty() {
+            return nick.to_lowercase();
+        }
+
+        // Contacts without a name are named by their first address.
+        let address = contact.emails.values().next().map_or("", String::as_str);
+        sanitize_nick(address.split('@').next().unwrap_or_default()).to_lowercase()
+    }
+}
+
+impl MatchAction for MuttAliases {
+    fn process(&self, contacts: Vec<&mut Contact>) -> Result<bool> {
+        let mut aliases = Vec::new();
+        for contact in contacts.iter() {
+            let nick = self.nick(contact);
+            for (index, (email_name, address)) in contact.emails.iter().enumerate() {
+                let alias = match sanitize_nick(email_name).to_lowercase() {
+                    _ if index == 0 => nick.clone(),
+                    suffix if suffix.is_empty() => format!("{}-{}", nick, index + 1),
+                    suffix => format!("{}-{}", nick, suffix),
+                };
+                let address = email::normalize(address);
+                let recipient = match contact.name() {
+                    Some(name) => format!("{} <{}>", quote_name(name), address),
+                    None => format!("<{}>", address),
+                };
+                aliases.push((alias, contact.id(), recipient));
+            }
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (alias, _, _) in aliases.iter() {
+            *counts.entry(alias.as_str()).or_default() += 1;
+        }
+
+        let mut used = HashSet::new();
+        for (alias, id, recipient) in aliases.iter() {
+            let alias = if counts[alias.as_str()] > 1 {
+                format!("{}-{}", alias, short_id(id))
+            } else {
+                alias.clone()
+            };
+            println!("alias {} {}", unique_alias(&mut used, alias), recipient);
+        }
+
+        Ok(false)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+/// Replace characters which cannot be part of a Mutt alias with `-`.
+fn sanitize_nick(nick: &str) -> String {
+    let nick: String = nick
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.+".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    nick.trim_matches('-').to_owned()
+}
+
+/// Beginning of a contact ID used to tell apart contacts with the same alias.
+fn short_id(id: &str) -> String {
+    sanitize_nick(&id.chars().take(8).collect::<String>()).to_lowercase()
+}
+
+/// Append a number to an alias if it is already used and mark the result as
+/// used. This is a last resort for aliases which are not unique even with
+/// the contact ID, see `short_id()`.
+fn unique_alias(used: &mut HashSet<String>, alias: String) -> String {
+    let mut unique = alias.clone();
+    let mut counter = 1;
+    while used.contains(&unique) {
+        counter += 1;
+        unique = format!("{}{}", alias, counter);
+    }
+    used.insert(unique.clone());
+    unique
+}
+
+/// Quote a display name so that it can be used in an alias line of a muttrc
+/// file. The name is quoted if it contains characters with special meaning
+/// in e-mail headers (RFC 5322 `specials`) or in muttrc (comments and
+/// quotes). Backticks, `$` and backslashes are escaped, so that Mutt neither
+/// runs commands nor expands variables when the line is sourced.
+fn quote_name(name: &str) -> String {
+    let name = if name.chars().any(|c| "()<>[]:;@\\,.\"#'".contains(c)) {
+        let mut quoted = String::from("\"");
+        for c in name.chars() {
+            if c == '"' || c == '\\' {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        name.to_owned()
+    };
+
+    let mut escaped = String::new();
+    for c in name.chars() {
+        if "`$\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_plain_name() {
+        assert_eq!(quote_name("Jane Roe"), "Jane Roe");
+    }
+
+    #[test]
+    fn quote_name_with_specials() {
+        assert_eq!(quote_name("Roe, Jane"), "\"Roe, Jane\"");
+        assert_eq!(quote_name("J. \"JR\" Roe"), r#""J. \\"JR\\" Roe""#);
+        assert_eq!(quote_name(r"Jane \ Roe"), r#""Jane \\\\ Roe""#);
+    }
+
+    #[test]
+    fn quote_name_with_command_substitution() {
+        assert_eq!(
+            quote_name("Jane `touch /tmp/pwned` Roe"),
+            r"Jane \`touch /tmp/pwned\` Roe"
+        );
+    }
+
+    #[test]
+    fn quote_name_with_comment_and_variable() {
+        assert_eq!(quote_name("Alice #1 $HOME"), r#""Alice #1 \$HOME""#);
+        assert_eq!(quote_name("Bob's"), "\"Bob's\"");
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_nick(" John Doe! "), "John-Doe");
+        assert_eq!(sanitize_nick("j.d+work"), "j.d+work");
+    }
+
+    #[test]
+    fn short_id_of_uuid() {
+        assert_eq!(short_id("3F2A9C1E-7b6d-4d3c-9a8b-000000000000"), "3f2a9c1e");
+    }
+
+    #[test]
+    fn unique_alias_appends_number() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_alias(&mut used, "jd".to_owned()), "jd");
+        assert_eq!(unique_alias(&mut used, "jd".to_owned()), "jd2");
+        assert_eq!(unique_alias(&mut used, "jd".to_owned()), "jd3");
+    }
 }
@@ -0,0 +1,185 @@
+use phonenumber::{country, Mode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A telephone number as entered by the user together with its normalized
+/// E.164 form. The normalized form is missing if the number could not be
+/// parsed.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "TelRepr")]
+pub struct Tel {
+    number: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e164: Option<String>,
+}
+
+/// Serialized form of `Tel`. Older contact files store telephone numbers as
+/// plain strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TelRepr {
+    Plain(String),
+    Full {
+        number: String,
+        #[serde(default)]
+        e164: Option<String>,
+    },
+}
+
+impl From<TelRepr> for Tel {
+    fn from(repr: TelRepr) -> Self {
+        match repr {
+            TelRepr::Plain(number) => Self { number, e164: None },
+            TelRepr::Full { number, e164 } => Self { number, e164 },
+        }
+    }
+}
+
+impl Tel {
+    /// Create a new telephone number and compute its normalized form.
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - telephone number in an arbitrary human readable format.
+    ///
+    /// * `country` - country used for numbers without an international
+    ///   calling code.
+    pub fn parse(number: String, country: Option<country::Id>) -> Self {
+        let e164 = normalize(&number, country);
+        Self { number, e164 }
+    }
+
+    /// Telephone number in the form it was entered.
+    pub fn number(&self) -> &str {
+        self.number.as_str()
+    }
+
+    /// Normalized E.164 form of the number (e.g. `+420603123456`).
+    pub fn e164(&self) -> Option<&str> {
+        self.e164.as_ref().map(String::as_ref)
+    }
+
+    /// Returns true if the stored normalized form matches the number.
+    pub fn is_normalized(&self, country: Option<country::Id>) -> bool {
+        self.e164 == normalize(&self.number, country)
+    }
+
+    /// Recompute the normalized form of the number.
+    pub fn normalize(&mut self, country: Option<country::Id>) {
+        self.e164 = normalize(&self.number, country);
+    }
+
+    /// Returns true if the number is a valid (assigned) telephone number.
+    pub fn is_valid(&self, country: Option<country::Id>) -> bool {
+        match phonenumber::parse(country, &self.number) {
+            Ok(number) => phonenumber::is_valid(&number),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if both numbers are the same telephone number.
+    pub fn matches(&self, other: &Tel) -> bool {
+        match (self.e164(), other.e164()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.number == other.number,
+        }
+    }
+
+    /// Print a warning to stderr if the number is not valid.
+    pub fn warn_if_invalid(&self, country: Option<country::Id>) {
+        if self.e164.is_none() {
+            eprintln!(
+                "Warning: {} could not be parsed as a telephone number.",
+                self.number
+            );
+            if country.is_none() {
+                eprintln!(
+                    "Numbers without an international calling code require \
+                     defaultCountry to be configured."
+                );
+            }
+        } else if !self.is_valid(country) {
+            eprintln!(
+                "Warning: {} does not seem to be a valid telephone number.",
+                self.number
+            );
+        }
+    }
+}
+
+impl fmt::Display for Tel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.number)?;
+        match self.e164() {
+            Some(e164) if e164 != self.number => write!(f, " ({})", e164),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Return E.164 form of a telephone number or None if it cannot be parsed.
+fn normalize(number: &str, country: Option<country::Id>) -> Option<String> {
+    phonenumber::parse(country, number)
+        .ok()
+        .map(|number| number.format().mode(Mode::E164).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn national_number() {
+        let tel = Tel::parse(String::from("603 123 456"), Some(country::Id::CZ));
+        assert_eq!(tel.e164(), Some("+420603123456"));
+        assert!(tel.is_normalized(Some(country::Id::CZ)));
+        assert_eq!(tel.to_string(), "603 123 456 (+420603123456)");
+
+        let tel = Tel::parse(String::from("603 123 456"), None);
+        assert_eq!(tel.e164(), None);
+    }
+
+    #[test]
+    fn international_number() {
+        let tel = Tel::parse(String::from("+420 603 123 456"), None);
+        assert_eq!(tel.e164(), Some("+420603123456"));
+        assert!(tel.is_valid(None));
+
+        let other = Tel::parse(String::from("603123456"), Some(country::Id::CZ));
+        assert!(tel.matches(&other));
+
+        let tel = Tel::parse(String::from("+420603123456"), Some(country::Id::US));
+        assert_eq!(tel.to_string(), "+420603123456");
+    }
+
+    #[test]
+    fn unparseable_number() {
+        let tel = Tel::parse(String::from("call me maybe"), Some(country::Id::CZ));
+        assert_eq!(tel.e164(), None);
+        assert!(!tel.is_valid(Some(country::Id::CZ)));
+        assert!(tel.is_normalized(Some(country::Id::CZ)));
+        assert!(!tel.matches(&Tel::parse(String::from("call me"), None)));
+        assert_eq!(
+            serde_json::to_value(&tel).unwrap(),
+            serde_json::json!({"number": "call me maybe"})
+        );
+    }
+
+    #[test]
+    fn legacy_plain_string() {
+        let mut tel: Tel = serde_json::from_str(r#""603 123 456""#).unwrap();
+        assert_eq!(tel.number(), "603 123 456");
+        assert_eq!(tel.e164(), None);
+        assert!(!tel.is_normalized(Some(country::Id::CZ)));
+
+        tel.normalize(Some(country::Id::CZ));
+        assert_eq!(tel.e164(), Some("+420603123456"));
+        assert_eq!(
+            serde_json::to_value(&tel).unwrap(),
+            serde_json::json!({"number": "603 123 456", "e164": "+420603123456"})
+        );
+
+        let tel: Tel = serde_json::from_str(r#"{"number": "+420603123456"}"#).unwrap();
+        assert_eq!(tel.e164(), None);
+    }
+}
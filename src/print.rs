@@ -26,4 +26,8 @@ impl MatchAction for PrintExporter {
 
         Ok(false)
     }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
 }
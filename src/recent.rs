@@ -0,0 +1,34 @@
+use crate::contact;
+use crate::storage::ContactStore;
+use anyhow::Result;
+
+/// Print recently created or modified contacts, the most recent first.
+///
+/// # Arguments
+///
+/// * `limit` - maximum number of printed contacts.
+pub fn recent(store: &dyn ContactStore, limit: usize) -> Result<()> {
+    let contacts = store.load_valid()?;
+
+    let mut touched: Vec<_> = contacts
+        .contacts()
+        .iter()
+        .filter_map(|contact| {
+            contact
+                .modified()
+                .or_else(|| contact.created())
+                .map(|time| (time, contact))
+        })
+        .collect();
+    touched.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+
+    for (time, contact) in touched.into_iter().take(limit) {
+        println!(
+            "{}\t{}",
+            contact::format_time(time),
+            contact.name().unwrap_or("<unnamed>")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use crate::contact::Contacts;
+use crate::storage::ContactStore;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+/// Add a relation between two contacts.
+///
+/// # Arguments
+///
+/// * `from` - a regular expression matching name of exactly one contact the
+///   relation is added to.
+/// * `kind` - type of the relation, e.g. `spouse` or `works-at`.
+/// * `to` - a regular expression matching name of exactly one related
+///   contact.
+pub fn add(store: &dyn ContactStore, from: &str, kind: &str, to: &str) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+    let from = find_one(&contacts, from)?;
+    let to = find_one(&contacts, to)?;
+
+    let other = &contacts.contacts()[to];
+    let other_id = other.id().to_owned();
+    let other_name = other.name().map(String::from);
+
+    contacts.contacts_mut()[from].add_relation(kind.to_owned(), other_id, other_name)?;
+    store.save(&mut contacts)?;
+
+    eprintln!("Relation successfully added.");
+    Ok(())
+}
+
+/// Remove a relation between two contacts. See `add()`.
+pub fn remove(store: &dyn ContactStore, from: &str, kind: &str, to: &str) -> Result<()> {
+    let mut contacts = store.load_valid()?;
+    let from = find_one(&contacts, from)?;
+    let to = find_one(&contacts, to)?;
+
+    let other_id = contacts.contacts()[to].id().to_owned();
+    if !contacts.contacts_mut()[from].remove_relation(kind, &other_id) {
+        bail!("No such relation exists.");
+    }
+    store.save(&mut contacts)?;
+
+    eprintln!("Relation successfully removed.");
+    Ok(())
+}
+
+/// Return index of the only contact whose name matches a regular expression.
+fn find_one(contacts: &Contacts, name_regex: &str) -> Result<usize> {
+    let regex = Regex::new(name_regex).context("Invalid name regex.")?;
+
+    let mut matches = contacts
+        .contacts()
+        .iter()
+        .enumerate()
+        .filter(|(_, contact)| contact.name().is_some_and(|name| regex.is_match(name)))
+        .map(|(index, _)| index);
+
+    let index = match matches.next() {
+        Some(index) => index,
+        None => bail!("No contact matches {}.", name_regex),
+    };
+    if matches.next().is_some() {
+        bail!("More than one contact matches {}.", name_regex);
+    }
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    fn store() -> MemoryStore {
+        let contact = |id: &str, full_name: &str| {
+            json!({
+                "id": id,
+                "fullName": full_name,
+                "entityName": null,
+                "tels": {},
+                "emails": {},
+                "labels": {},
+            })
+        };
+        MemoryStore::with_contacts(json!({
+            "contacts": [contact("john", "John Doe"), contact("jane", "Jane Roe")],
+        }))
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let store = store();
+        add(&store, "John", "spouse", "Jane").unwrap();
+        add(&store, "John", "spouse", "Jane").unwrap();
+
+        let contacts = store.load_valid().unwrap();
+        let relations = contacts.get("john").unwrap().relations();
+        assert_eq!(relations.len(), 1);
+        assert_eq!(relations[0].kind, "spouse");
+        assert_eq!(relations[0].contact, "jane");
+
+        remove(&store, "John", "spouse", "Jane").unwrap();
+        assert!(remove(&store, "John", "spouse", "Jane").is_err());
+        let contacts = store.load_valid().unwrap();
+        assert!(contacts.get("john").unwrap().relations().is_empty());
+    }
+
+    #[test]
+    fn ambiguous_name() {
+        let store = store();
+        let error = add(&store, "J", "spouse", "Jane").unwrap_err();
+        assert_eq!(error.to_string(), "More than one contact matches J.");
+        let error = add(&store, "John", "spouse", "Alice").unwrap_err();
+        assert_eq!(error.to_string(), "No contact matches Alice.");
+    }
+}
@@ -1,11 +1,62 @@
 use crate::actions::MatchAction;
-use crate::contact::{Contact, Contacts};
-use anyhow::Result;
+use crate::contact::{check_errors, Contact, Contacts};
+use crate::email;
+use crate::phone::Tel;
+use crate::storage::{ContactStore, Query};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, TimeZone, Utc};
+use phonenumber::country;
 use regex::Regex;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Order of search results.
+pub enum SortKey {
+    /// Full name or entity name.
+    Name,
+    /// Last word of full name. Entities are sorted by their name.
+    Surname,
+    /// Entity name or organization of a person.
+    Entity,
+    /// Time of the last modification.
+    Modified,
+    /// Time of creation.
+    Created,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "name" => Ok(SortKey::Name),
+            "surname" => Ok(SortKey::Surname),
+            "entity" => Ok(SortKey::Entity),
+            "modified" => Ok(SortKey::Modified),
+            "created" => Ok(SortKey::Created),
+            _ => bail!("Unknown sort key: {}", value),
+        }
+    }
+}
 
 pub struct SearchOptions {
     full_name_regex: Option<Regex>,
     entity_name_regex: Option<Regex>,
+    tel: Option<Tel>,
+    email: Option<String>,
+    text_regex: Option<Regex>,
+    require_email: bool,
+    related_to_regex: Option<Regex>,
+    group: Option<String>,
+    tags: Vec<String>,
+    any_tag: bool,
+    modified_since: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    lenient: bool,
+    sort: Option<SortKey>,
+    reverse: bool,
+    offset: usize,
+    limit: Option<usize>,
 }
 
 impl SearchOptions {
@@ -13,6 +64,21 @@ impl SearchOptions {
         SearchOptions {
             full_name_regex: None,
             entity_name_regex: None,
+            tel: None,
+            email: None,
+            text_regex: None,
+            require_email: false,
+            related_to_regex: None,
+            group: None,
+            tags: Vec::new(),
+            any_tag: false,
+            modified_since: None,
+            created_before: None,
+            lenient: false,
+            sort: None,
+            reverse: false,
+            offset: 0,
+            limit: None,
         }
     }
 
@@ -25,55 +91,391 @@ impl SearchOptions {
         self.entity_name_regex = Some(Regex::new(entity_name_regex)?);
         Ok(())
     }
+
+    /// Include only contacts related to a contact whose name matches the
+    /// regex. Relations in both directions are considered.
+    pub fn set_related_to_regex(&mut self, related_to_regex: &str) -> Result<()> {
+        self.related_to_regex = Some(Regex::new(related_to_regex)?);
+        Ok(())
+    }
+
+    /// Include only members of a group, including members of nested
+    /// groups.
+    pub fn set_group(&mut self, group: &str) {
+        self.group = Some(group.to_owned());
+    }
+
+    /// Include only contacts with given tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags` - required tags.
+    /// * `any_tag` - if true, contacts with at least one of the tags are
+    ///   included. Otherwise, contacts must have all the tags.
+    pub fn set_tags(&mut self, tags: Vec<String>, any_tag: bool) {
+        self.tags = tags;
+        self.any_tag = any_tag;
+    }
+
+    /// Include only contacts modified at or after the given time. Contacts
+    /// without modification time are excluded.
+    pub fn set_modified_since(&mut self, time: DateTime<Utc>) {
+        self.modified_since = Some(time);
+    }
+
+    /// Include only contacts created before the given time. Contacts without
+    /// creation time are included.
+    pub fn set_created_before(&mut self, time: DateTime<Utc>) {
+        self.created_before = Some(time);
+    }
+
+    /// Load contacts which do not satisfy contact invariants instead of
+    /// failing. Such contacts are reported and excluded from the search.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Sort search results. Results are in the order of the contacts file
+    /// if this is not called.
+    ///
+    /// # Arguments
+    ///
+    /// * `sort` - sort key.
+    /// * `reverse` - sort in descending order.
+    pub fn set_sort(&mut self, sort: SortKey, reverse: bool) {
+        self.sort = Some(sort);
+        self.reverse = reverse;
+    }
+
+    /// Skip first `offset` search results and pass at most `limit` results
+    /// to the match action.
+    pub fn set_page(&mut self, offset: usize, limit: Option<usize>) {
+        self.offset = offset;
+        self.limit = limit;
+    }
+
+    pub fn set_tel(&mut self, tel: &str, country: Option<country::Id>) {
+        self.tel = Some(Tel::parse(tel.to_owned(), country));
+    }
+
+    /// Include only contacts with an e-mail address. Addresses are compared
+    /// in their normalized form.
+    pub fn set_email(&mut self, address: &str) {
+        self.email = Some(email::normalize(address));
+    }
+
+    /// Include only contacts whose name, e-mail address or label value
+    /// contains a text (case insensitive).
+    pub fn set_text(&mut self, text: &str) {
+        let pattern = format!("(?i){}", regex::escape(text));
+        self.text_regex = Some(Regex::new(&pattern).expect("Escaped text is a valid regex."));
+    }
+
+    /// Include only contacts with at least one e-mail address.
+    pub fn set_require_email(&mut self, require_email: bool) {
+        self.require_email = require_email;
+    }
 }
 
-pub fn search(options: SearchOptions, action: Box<dyn MatchAction>) -> Result<()> {
-    let mut contacts = Contacts::load_from_home()?;
+pub fn search(
+    store: &dyn ContactStore,
+    options: SearchOptions,
+    action: Box<dyn MatchAction>,
+) -> Result<()> {
+    let query = Query {
+        full_name_regex: options.full_name_regex.as_ref(),
+        entity_name_regex: options.entity_name_regex.as_ref(),
+        tel: options.tel.as_ref(),
+        email: options.email.as_deref(),
+        text_regex: options.text_regex.as_ref(),
+    };
 
-    let results: Vec<&mut Contact> = contacts
+    // Actions which may save contacts need all of them. Relations are
+    // searched through all contacts.
+    let (mut contacts, errors) = if action.is_read_only() && options.related_to_regex.is_none() {
+        store.load_matching(&query)?
+    } else {
+        store.load()?
+    };
+    if !options.lenient {
+        check_errors(&errors)?;
+    }
+
+    for error in errors.iter() {
+        eprintln!("Warning: skipping invalid {}", error);
+    }
+
+    let mut id_filters: Vec<HashSet<String>> = Vec::new();
+    if let Some(regex) = &options.related_to_regex {
+        id_filters.push(related_ids(&contacts, regex));
+    }
+    if let Some(group) = &options.group {
+        id_filters.push(contacts.group_members(group)?);
+    }
+
+    let mut results: Vec<&mut Contact> = contacts
         .contacts_mut()
         .iter_mut()
-        .filter(|contact| is_match(contact, &options))
+        .enumerate()
+        .filter(|(index, _)| !errors.iter().any(|error| error.index == *index))
+        .map(|(_, contact)| contact)
+        .filter(|contact| is_match(contact, &query, &options, &id_filters))
+        .collect();
+
+    if let Some(sort) = &options.sort {
+        results.sort_by_cached_key(|contact| sort_key(contact, sort));
+        if options.reverse {
+            results.reverse();
+        }
+    }
+
+    let results: Vec<&mut Contact> = results
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit.unwrap_or(usize::MAX))
         .collect();
 
     let save = action.process(results)?;
     if save {
-        contacts.save_to_home()?;
+        contacts.remove_marked();
+        store.save(&mut contacts)?;
     }
 
     Ok(())
 }
 
-fn is_match(contact: &Contact, options: &SearchOptions) -> bool {
-    if let Some(full_name_regex) = &options.full_name_regex {
-        match contact.full_name() {
-            Some(full_name) => {
-                if !full_name_regex.is_match(full_name) {
-                    return false;
-                }
+/// Return a key for sorting of search results. Contacts without the sort
+/// property are sorted last. Contacts with equal sort property are sorted by
+/// name.
+fn sort_key(contact: &Contact, sort: &SortKey) -> (bool, Option<String>, Option<String>) {
+    let value = match sort {
+        SortKey::Name => contact.name().map(str::to_lowercase),
+        SortKey::Surname => match contact.full_name() {
+            Some(full_name) => full_name.split_whitespace().last(),
+            None => contact.entity_name(),
+        }
+        .map(str::to_lowercase),
+        SortKey::Entity => contact
+            .entity_name()
+            .or_else(|| contact.organization())
+            .map(str::to_lowercase),
+        SortKey::Modified => contact.modified().map(format_sortable),
+        SortKey::Created => contact.created().map(format_sortable),
+    };
+    let name = contact.name().map(str::to_lowercase);
+    (value.is_none(), value, name)
+}
+
+/// Format time so that lexicographic order of formatted times corresponds
+/// to their chronological order.
+fn format_sortable(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Parse time given either in RFC 3339 format or as a date (YYYY-MM-DD), in
+/// which case midnight of local time is used.
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let date = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => bail!("Invalid time {}, expected YYYY-MM-DD or RFC 3339.", value),
+    };
+    match Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        .earliest()
+    {
+        Some(time) => Ok(time.with_timezone(&Utc)),
+        None => bail!("Invalid local time: {}", value),
+    }
+}
+
+/// Return IDs of all contacts related to a contact whose name matches the
+/// regex.
+fn related_ids(contacts: &Contacts, regex: &Regex) -> HashSet<String> {
+    let targets: HashSet<&str> = contacts
+        .contacts()
+        .iter()
+        .filter(|contact| contact.name().is_some_and(|name| regex.is_match(name)))
+        .map(Contact::id)
+        .collect();
+
+    let mut related = HashSet::new();
+    for contact in contacts.contacts() {
+        let is_target = targets.contains(contact.id());
+        for relation in contact.relations() {
+            if is_target {
+                related.insert(relation.contact.clone());
             }
-            None => {
-                if options.entity_name_regex.is_none() {
-                    return false;
-                }
+            if targets.contains(relation.contact.as_str()) {
+                related.insert(contact.id().to_owned());
             }
         }
     }
 
-    if let Some(entity_name_regex) = &options.entity_name_regex {
-        match contact.entity_name() {
-            Some(entity_name) => {
-                if !entity_name_regex.is_match(entity_name) {
-                    return false;
-                }
-            }
-            None => {
-                if options.full_name_regex.is_none() {
-                    return false;
+    related
+}
+
+/// Returns true if the contact matches search options.
+///
+/// # Arguments
+///
+/// * `contact` - contact to be checked.
+/// * `options` - search options.
+/// * `id_filters` - the contact matches only if its ID is in all of the
+///   sets.
+fn is_match(
+    contact: &Contact,
+    query: &Query,
+    options: &SearchOptions,
+    id_filters: &[HashSet<String>],
+) -> bool {
+    if !query.is_match(contact) {
+        return false;
+    }
+
+    if options.require_email && contact.emails.is_empty() {
+        return false;
+    }
+
+    if let Some(modified_since) = options.modified_since {
+        if contact
+            .modified()
+            .is_none_or(|modified| modified < modified_since)
+        {
+            return false;
+        }
+    }
+
+    if let Some(created_before) = options.created_before {
+        if contact
+            .created()
+            .is_some_and(|created| created >= created_before)
+        {
+            return false;
+        }
+    }
+
+    if !options.tags.is_empty() {
+        let mut tags = options.tags.iter();
+        let has_tags = if options.any_tag {
+            tags.any(|tag| contact.tags.contains(tag))
+        } else {
+            tags.all(|tag| contact.tags.contains(tag))
+        };
+        if !has_tags {
+            return false;
+        }
+    }
+
+    if !id_filters.iter().all(|ids| ids.contains(contact.id())) {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Action which records names of matches and optionally deletes them.
+    struct Record {
+        names: Rc<RefCell<Vec<String>>>,
+        delete: bool,
+    }
+
+    impl MatchAction for Record {
+        fn process(&self, contacts: Vec<&mut Contact>) -> Result<bool> {
+            for contact in contacts {
+                self.names
+                    .borrow_mut()
+                    .push(contact.name().unwrap_or_default().to_owned());
+                if self.delete {
+                    contact.delete();
                 }
             }
+            Ok(self.delete)
+        }
+
+        fn is_read_only(&self) -> bool {
+            !self.delete
         }
     }
 
-    return true;
+    fn contact(full_name: &str, tags: &[&str]) -> Value {
+        json!({
+            "fullName": full_name,
+            "entityName": null,
+            "tels": {},
+            "emails": {},
+            "labels": {},
+            "tags": tags,
+        })
+    }
+
+    fn store() -> MemoryStore {
+        MemoryStore::with_contacts(json!({
+            "contacts": [
+                contact("John Doe", &["work"]),
+                contact("Jane Roe", &["family"]),
+                contact("Alice Doe", &["family", "work"]),
+            ],
+        }))
+    }
+
+    fn run(store: &MemoryStore, options: SearchOptions, delete: bool) -> Vec<String> {
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let action = Record {
+            names: Rc::clone(&names),
+            delete,
+        };
+        search(store, options, Box::new(action)).unwrap();
+        let names = names.borrow().clone();
+        names
+    }
+
+    #[test]
+    fn search_by_name() {
+        let mut options = SearchOptions::new();
+        options.set_full_name_regex("Doe$").unwrap();
+        assert_eq!(run(&store(), options, false), ["John Doe", "Alice Doe"]);
+    }
+
+    #[test]
+    fn search_by_tags() {
+        let mut options = SearchOptions::new();
+        options.set_tags(vec![String::from("family"), String::from("work")], false);
+        assert_eq!(run(&store(), options, false), ["Alice Doe"]);
+    }
+
+    #[test]
+    fn search_sorted_page() {
+        let mut options = SearchOptions::new();
+        options.set_sort(SortKey::Name, false);
+        options.set_page(1, Some(1));
+        assert_eq!(run(&store(), options, false), ["Jane Roe"]);
+    }
+
+    #[test]
+    fn search_text() {
+        let mut options = SearchOptions::new();
+        options.set_text("ROE");
+        assert_eq!(run(&store(), options, false), ["Jane Roe"]);
+    }
+
+    #[test]
+    fn modified_matches_are_saved() {
+        let store = store();
+        let mut options = SearchOptions::new();
+        options.set_full_name_regex("^J").unwrap();
+        assert_eq!(run(&store, options, true), ["John Doe", "Jane Roe"]);
+        assert_eq!(run(&store, SearchOptions::new(), false), ["Alice Doe"]);
+    }
 }
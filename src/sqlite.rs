@@ -0,0 +1,543 @@
+use crate::contact::{Contact, ContactError, Contacts, Group};
+use crate::email;
+use crate::storage::{self, ContactStore, Query};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, Error, Transaction};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contacts (
+    id TEXT PRIMARY KEY,
+    full_name TEXT,
+    entity_name TEXT,
+    sort_key TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS contacts_full_name ON contacts (full_name);
+CREATE INDEX IF NOT EXISTS contacts_entity_name ON contacts (entity_name);
+CREATE INDEX IF NOT EXISTS contacts_sort_key ON contacts (sort_key, id);
+
+CREATE TABLE IF NOT EXISTS emails (
+    contact_id TEXT NOT NULL,
+    address TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS emails_contact_id ON emails (contact_id);
+CREATE INDEX IF NOT EXISTS emails_address ON emails (address);
+
+CREATE TABLE IF NOT EXISTS tels (
+    contact_id TEXT NOT NULL,
+    number TEXT NOT NULL,
+    e164 TEXT
+);
+CREATE INDEX IF NOT EXISTS tels_contact_id ON tels (contact_id);
+CREATE INDEX IF NOT EXISTS tels_number ON tels (number);
+CREATE INDEX IF NOT EXISTS tels_e164 ON tels (e164);
+
+CREATE TABLE IF NOT EXISTS groups (
+    name TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+";
+
+/// Contacts stored in an SQLite database. Each contact is stored as its
+/// serialized form together with indexed names, e-mail addresses and
+/// telephone numbers, so that searches load only matching contacts, see
+/// `ContactStore::load_matching()`.
+pub struct Sqlite {
+    path: PathBuf,
+}
+
+impl Sqlite {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Open the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `create` - create the database if it does not exist.
+    fn connect(&self, create: bool) -> Result<Connection> {
+        if !create && !self.path.exists() {
+            bail!("Could not load contacts. Make sure contacts are initialized.");
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| {
+                format!(
+                    "Error during creation of directory with contacts: {}",
+                    dir.display()
+                )
+            })?;
+        }
+
+        let connection = Connection::open(&self.path)
+            .with_context(|| format!("Could not open {}.", self.path.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("Could not create the contacts database.")?;
+        connection.create_scalar_function(
+            "regexp",
+            2,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |context| {
+                let regex = context.get_or_create_aux(0, |pattern| -> Result<Regex> {
+                    Ok(Regex::new(pattern.as_str()?)?)
+                })?;
+                match context.get_raw(1) {
+                    ValueRef::Text(text) => {
+                        let text = std::str::from_utf8(text)
+                            .map_err(|error| Error::UserFunctionError(error.into()))?;
+                        Ok(regex.is_match(text))
+                    }
+                    _ => Ok(false),
+                }
+            },
+        )?;
+        Ok(connection)
+    }
+
+    /// Load contacts selected by a condition on table `contacts` aliased as
+    /// `c`, together with all groups.
+    fn select(
+        connection: &Connection,
+        condition: &str,
+        values: Vec<Value>,
+    ) -> Result<(Contacts, Vec<ContactError>)> {
+        let sql = format!(
+            "SELECT id, data FROM contacts AS c WHERE {} ORDER BY sort_key, id",
+            condition
+        );
+        let mut statement = connection.prepare(&sql)?;
+        let mut rows = statement.query(params_from_iter(values))?;
+        let mut contacts = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let data: String = row.get(1)?;
+            contacts.push(parse_contact(&id, &data)?);
+        }
+
+        let mut statement = connection.prepare("SELECT data FROM groups ORDER BY name")?;
+        let mut rows = statement.query([])?;
+        let mut groups: Vec<Group> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let data: String = row.get(0)?;
+            groups.push(serde_json::from_str(&data).context("Invalid group in the database.")?);
+        }
+
+        Ok(Contacts::from_parts(contacts, groups))
+    }
+}
+
+impl ContactStore for Sqlite {
+    fn exists(&self) -> Result<bool> {
+        Ok(self.path.exists())
+    }
+
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)> {
+        Self::select(&self.connect(false)?, "1", Vec::new())
+    }
+
+    fn load_matching(&self, query: &Query) -> Result<(Contacts, Vec<ContactError>)> {
+        let (condition, values) = query_condition(query, "m");
+        let condition = format!(
+            "c.id IN (
+                SELECT m.id FROM contacts AS m WHERE {condition}
+                UNION
+                SELECT json_extract(r.value, '$.contact')
+                FROM contacts AS m, json_each(m.data, '$.relations') AS r
+                WHERE {condition}
+            )",
+            condition = condition
+        );
+        Self::select(&self.connect(false)?, &condition, values)
+    }
+
+    fn save(&self, contacts: &mut Contacts) -> Result<()> {
+        let mut connection = self.connect(true)?;
+        let transaction = connection.transaction()?;
+
+        let mut stored: HashMap<String, String> = HashMap::new();
+        {
+            let mut statement = transaction.prepare("SELECT id, data FROM contacts")?;
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                stored.insert(row.get(0)?, row.get(1)?);
+            }
+        }
+
+        for contact in contacts.contacts_mut() {
+            if let Some(previous) = stored.remove(contact.id()) {
+                let previous = serde_json::from_str(&previous).with_context(|| {
+                    format!("Invalid contact {} in the database.", contact.id())
+                })?;
+                if !contact.touch_if_changed(&previous) {
+                    continue;
+                }
+            }
+            write_contact(&transaction, contact)?;
+        }
+
+        for id in stored.keys() {
+            delete_contact(&transaction, id)?;
+        }
+
+        transaction.execute("DELETE FROM groups", [])?;
+        for group in contacts.groups() {
+            transaction.execute(
+                "INSERT INTO groups (name, data) VALUES (?1, ?2)",
+                params![group.name, serde_json::to_string(group)?],
+            )?;
+        }
+
+        transaction.commit().context("Error while saving contacts.")
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Contact>> {
+        // Related contacts are loaded to resolve names of relations.
+        let condition = "c.id = ?1 OR c.id IN (
+                SELECT json_extract(r.value, '$.contact')
+                FROM contacts AS m, json_each(m.data, '$.relations') AS r
+                WHERE m.id = ?1
+            )";
+        let (contacts, _) = Self::select(
+            &self.connect(false)?,
+            condition,
+            vec![Value::from(id.to_owned())],
+        )?;
+        Ok(contacts.get(id).cloned())
+    }
+
+    fn upsert(&self, mut contact: Contact) -> Result<()> {
+        if let Some((field, message)) = contact.validate() {
+            bail!("Invalid contact ({}): {}", field, message);
+        }
+
+        let mut connection = self.connect(true)?;
+        let transaction = connection.transaction()?;
+        let previous: Option<String> = transaction
+            .query_row(
+                "SELECT data FROM contacts WHERE id = ?1",
+                [contact.id()],
+                |row| row.get(0),
+            )
+            .ok();
+        if let Some(previous) = previous {
+            let previous = serde_json::from_str(&previous)
+                .with_context(|| format!("Invalid contact {} in the database.", contact.id()))?;
+            if !contact.touch_if_changed(&previous) {
+                return Ok(());
+            }
+        }
+        write_contact(&transaction, &contact)?;
+        transaction.commit().context("Error while saving contacts.")
+    }
+
+    fn backup(&self) -> Result<Vec<PathBuf>> {
+        Ok(vec![storage::backup(&self.path)?])
+    }
+
+    fn contact_path(&self, _id: &str) -> Result<String> {
+        bail!("Contacts in SQLite storage are not stored in files.");
+    }
+}
+
+/// SQL condition equivalent to `Query::is_match()` on table `contacts`
+/// aliased as `alias` together with its parameters.
+fn query_condition(query: &Query, alias: &str) -> (String, Vec<Value>) {
+    let mut conditions = vec![String::from("1")];
+    let mut values = Vec::new();
+
+    let mut name_condition = |regex: Option<&Regex>, column: &str, other_given: bool| {
+        if let Some(regex) = regex {
+            values.push(Value::from(regex.as_str().to_owned()));
+            let matches = format!("{}.{} REGEXP ?{}", alias, column, values.len());
+            if other_given {
+                conditions.push(format!("({}.{} IS NULL OR {})", alias, column, matches));
+            } else {
+                conditions.push(matches);
+            }
+        }
+    };
+    name_condition(
+        query.full_name_regex,
+        "full_name",
+        query.entity_name_regex.is_some(),
+    );
+    name_condition(
+        query.entity_name_regex,
+        "entity_name",
+        query.full_name_regex.is_some(),
+    );
+
+    if let Some(tel) = query.tel {
+        values.push(Value::from(tel.number().to_owned()));
+        let number = values.len();
+        values.push(match tel.e164() {
+            Some(e164) => Value::from(e164.to_owned()),
+            None => Value::Null,
+        });
+        let e164 = values.len();
+        conditions.push(format!(
+            "{alias}.id IN (
+                SELECT contact_id FROM tels WHERE e164 = ?{e164}
+                UNION
+                SELECT contact_id FROM tels
+                WHERE number = ?{number} AND (e164 IS NULL OR ?{e164} IS NULL)
+            )",
+            alias = alias,
+            number = number,
+            e164 = e164
+        ));
+    }
+
+    if let Some(address) = query.email {
+        values.push(Value::from(address.to_owned()));
+        conditions.push(format!(
+            "{}.id IN (SELECT contact_id FROM emails WHERE address = ?{})",
+            alias,
+            values.len()
+        ));
+    }
+
+    if let Some(text_regex) = query.text_regex {
+        values.push(Value::from(text_regex.as_str().to_owned()));
+        conditions.push(format!(
+            "({alias}.full_name REGEXP ?{n}
+                OR {alias}.entity_name REGEXP ?{n}
+                OR {alias}.id IN (SELECT contact_id FROM emails WHERE address REGEXP ?{n})
+                OR EXISTS (
+                    SELECT 1 FROM json_each({alias}.data, '$.labels') AS label
+                    WHERE label.value REGEXP ?{n}
+                ))",
+            alias = alias,
+            n = values.len()
+        ));
+    }
+
+    (conditions.join(" AND "), values)
+}
+
+fn parse_contact(id: &str, data: &str) -> Result<Contact> {
+    serde_json::from_str(data).with_context(|| format!("Invalid contact {} in the database.", id))
+}
+
+/// Insert or replace a contact including its index entries.
+fn write_contact(transaction: &Transaction, contact: &Contact) -> Result<()> {
+    delete_contact(transaction, contact.id())?;
+
+    let sort_key = contact.name().unwrap_or_default().to_lowercase();
+    transaction.execute(
+        "INSERT INTO contacts (id, full_name, entity_name, sort_key, data)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            contact.id(),
+            contact.full_name(),
+            contact.entity_name(),
+            sort_key,
+            serde_json::to_string(contact)?
+        ],
+    )?;
+    for address in contact.emails.values() {
+        transaction.execute(
+            "INSERT INTO emails (contact_id, address) VALUES (?1, ?2)",
+            params![contact.id(), email::normalize(address)],
+        )?;
+    }
+    for tel in contact.tels.values() {
+        transaction.execute(
+            "INSERT INTO tels (contact_id, number, e164) VALUES (?1, ?2, ?3)",
+            params![contact.id(), tel.number(), tel.e164()],
+        )?;
+    }
+    Ok(())
+}
+
+fn delete_contact(transaction: &Transaction, id: &str) -> Result<()> {
+    for table in ["emails", "tels"] {
+        transaction.execute(
+            &format!("DELETE FROM {} WHERE contact_id = ?1", table),
+            [id],
+        )?;
+    }
+    transaction.execute("DELETE FROM contacts WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phone::Tel;
+    use phonenumber::country;
+    use serde_json::json;
+
+    /// Database in a new temporary directory which is removed on drop.
+    struct TempDatabase {
+        dir: PathBuf,
+        store: Sqlite,
+    }
+
+    impl TempDatabase {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("con-rs-sqlite-{}-{}", std::process::id(), name));
+            let store = Sqlite::new(dir.join("contacts.sqlite"));
+            Self { dir, store }
+        }
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn contacts() -> Contacts {
+        let contacts = json!([
+            {
+                "id": "john",
+                "fullName": "John Doe",
+                "entityName": null,
+                "tels": {"mobile": {"number": "603 123 456", "e164": "+420603123456"}},
+                "emails": {"work": "john@acme.example"},
+                "labels": {"nick": "johnny"},
+                "relations": [{"type": "works-at", "contact": "acme"}],
+            },
+            {
+                "id": "jane",
+                "fullName": "Jane Roe",
+                "entityName": null,
+                "tels": {"home": {"number": "12 34"}},
+                "emails": {"home": "jane@example.com"},
+                "labels": {},
+            },
+            {
+                "id": "acme",
+                "fullName": null,
+                "entityName": "Acme Doe Inc.",
+                "tels": {},
+                "emails": {"info": "info@acme.example"},
+                "labels": {"web": "acme.example"},
+            },
+        ]);
+        let groups = json!([{"name": "work", "contacts": ["john", "acme"]}]);
+        let (contacts, errors) = Contacts::from_parts(
+            serde_json::from_value(contacts).unwrap(),
+            serde_json::from_value(groups).unwrap(),
+        );
+        assert!(errors.is_empty());
+        contacts
+    }
+
+    fn ids(contacts: &Contacts) -> Vec<&str> {
+        let mut ids: Vec<&str> = contacts.contacts().iter().map(Contact::id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn round_trip() {
+        let database = TempDatabase::new("round-trip");
+        let mut saved = contacts();
+        database.store.save(&mut saved).unwrap();
+
+        let loaded = database.store.load_valid().unwrap();
+        assert_eq!(ids(&loaded), ["acme", "jane", "john"]);
+        for contact in saved.contacts() {
+            assert_eq!(
+                serde_json::to_value(loaded.get(contact.id()).unwrap()).unwrap(),
+                serde_json::to_value(contact).unwrap()
+            );
+        }
+        assert_eq!(
+            serde_json::to_value(loaded.groups()).unwrap(),
+            serde_json::to_value(saved.groups()).unwrap()
+        );
+
+        let john = database.store.get("john").unwrap().unwrap();
+        assert!(john.to_string().contains("works-at: Acme Doe Inc."));
+        assert!(database.store.get("nobody").unwrap().is_none());
+    }
+
+    #[test]
+    fn query_condition_matches_query() {
+        let database = TempDatabase::new("query");
+        let mut contacts = contacts();
+        database.store.save(&mut contacts).unwrap();
+        let connection = database.store.connect(false).unwrap();
+
+        let doe = Regex::new("Doe").unwrap();
+        let text = Regex::new("(?i)JOHNNY|acme").unwrap();
+        let mobile = Tel::parse(String::from("603123456"), Some(country::Id::CZ));
+        let home = Tel::parse(String::from("12 34"), None);
+        let queries = [
+            Query {
+                full_name_regex: Some(&doe),
+                ..Query::default()
+            },
+            Query {
+                entity_name_regex: Some(&doe),
+                ..Query::default()
+            },
+            Query {
+                full_name_regex: Some(&doe),
+                entity_name_regex: Some(&doe),
+                ..Query::default()
+            },
+            Query {
+                email: Some("jane@example.com"),
+                ..Query::default()
+            },
+            Query {
+                tel: Some(&mobile),
+                ..Query::default()
+            },
+            Query {
+                tel: Some(&home),
+                ..Query::default()
+            },
+            Query {
+                text_regex: Some(&text),
+                ..Query::default()
+            },
+            Query {
+                full_name_regex: Some(&doe),
+                text_regex: Some(&text),
+                ..Query::default()
+            },
+        ];
+
+        for query in queries.iter() {
+            let (condition, values) = query_condition(query, "c");
+            let (selected, _) = Sqlite::select(&connection, &condition, values).unwrap();
+            let mut expected: Vec<&str> = contacts
+                .contacts()
+                .iter()
+                .filter(|contact| query.is_match(contact))
+                .map(Contact::id)
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(ids(&selected), expected, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn related_contacts_loaded() {
+        let database = TempDatabase::new("related");
+        database.store.save(&mut contacts()).unwrap();
+
+        let regex = Regex::new("John").unwrap();
+        let query = Query {
+            full_name_regex: Some(&regex),
+            ..Query::default()
+        };
+        let (loaded, errors) = database.store.load_matching(&query).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(ids(&loaded), ["acme", "john"]);
+        assert_eq!(loaded.groups().len(), 1);
+    }
+}
@@ -0,0 +1,693 @@
+use crate::config::Config;
+use crate::contact::{check_errors, Contact, ContactError, Contacts, Group, CONTACTS_FILE};
+use crate::email;
+use crate::encryption;
+use crate::index;
+use crate::phone::Tel;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::Sqlite;
+use crate::vdir::Vdir;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Directory with contact files of `ContactFiles` storage.
+pub const CONTACTS_DIR: &str = "contacts";
+/// Database of `Sqlite` storage.
+#[cfg(feature = "sqlite")]
+pub const SQLITE_FILE: &str = "contacts.sqlite";
+/// File with groups of `ContactFiles` storage.
+pub const GROUPS_FILE: &str = "groups.json";
+/// Git pathspecs matching files of all storages. Backups are excluded.
+const GIT_PATHSPECS: [&str; 2] = ["*.json", ":(exclude)*.bak/*"];
+
+/// Persistent storage of contacts. Commands work with contacts only through
+/// this trait, so that they do not depend on a particular backend.
+pub trait ContactStore {
+    /// Returns true if contacts have already been stored.
+    fn exists(&self) -> Result<bool>;
+
+    /// Load contacts. Contacts which do not satisfy contact invariants are
+    /// loaded as well and reported in the returned vector.
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)>;
+
+    /// Save contacts. Modification time is updated for all contacts which
+    /// differ from their stored version.
+    fn save(&self, contacts: &mut Contacts) -> Result<()>;
+
+    /// Move stored contacts aside, or copy them if they are shared with other
+    /// programs. Returns paths of the backups.
+    fn backup(&self) -> Result<Vec<PathBuf>>;
+
+    /// Path of the file with a contact relative to the directory with
+    /// contacts. Fails if the storage does not store contacts in the
+    /// directory with contacts.
+    fn contact_path(&self, id: &str) -> Result<String>;
+
+    /// Load contacts. All contacts must satisfy contact invariants.
+    fn load_valid(&self) -> Result<Contacts> {
+        let (contacts, errors) = self.load()?;
+        check_errors(&errors)?;
+        Ok(contacts)
+    }
+
+    /// Load contacts which may match a query. Other contacts may be loaded
+    /// as well, so the query has to be evaluated on the loaded contacts
+    /// again. All groups and all contacts related to the matching ones are
+    /// loaded too. Storages which cannot evaluate queries load all contacts.
+    fn load_matching(&self, _query: &Query) -> Result<(Contacts, Vec<ContactError>)> {
+        self.load()
+    }
+
+    /// Get a contact by its ID.
+    // Not used by any command yet, like `upsert()` and `delete()`, the
+    // method is part of the storage API.
+    #[allow(dead_code)]
+    fn get(&self, id: &str) -> Result<Option<Contact>> {
+        Ok(self.load_valid()?.get(id).cloned())
+    }
+
+    /// Save a single contact. A stored contact with the same ID is replaced.
+    #[allow(dead_code)]
+    fn upsert(&self, contact: Contact) -> Result<()> {
+        let mut contacts = self.load_valid()?;
+        contacts.upsert(contact);
+        self.save(&mut contacts)
+    }
+
+    /// Delete a contact by its ID. Relations to the contact and its group
+    /// memberships are removed as well. Returns false if there is no such
+    /// contact.
+    #[allow(dead_code)]
+    fn delete(&self, id: &str) -> Result<bool> {
+        let mut contacts = self.load_valid()?;
+        match contacts
+            .contacts_mut()
+            .iter_mut()
+            .find(|contact| contact.id() == id)
+        {
+            Some(contact) => contact.delete(),
+            None => return Ok(false),
+        }
+        contacts.remove_marked();
+        self.save(&mut contacts)?;
+        Ok(true)
+    }
+}
+
+/// Contacts file with unparsed contacts, see `SingleFile::load_matching()`.
+#[derive(Deserialize)]
+struct RawContacts<'a> {
+    #[serde(borrow)]
+    contacts: Vec<&'a RawValue>,
+    #[serde(default)]
+    groups: Vec<Group>,
+}
+
+/// Search conditions which can be evaluated by a storage, see
+/// `ContactStore::load_matching()`. The conditions have the same meaning as
+/// the corresponding search options.
+#[derive(Default)]
+pub struct Query<'a> {
+    pub full_name_regex: Option<&'a Regex>,
+    pub entity_name_regex: Option<&'a Regex>,
+    pub tel: Option<&'a Tel>,
+    /// Normalized e-mail address, see `email::normalize()`.
+    pub email: Option<&'a str>,
+    /// Regex matched against names, e-mail addresses and label values.
+    pub text_regex: Option<&'a Regex>,
+}
+
+impl Query<'_> {
+    /// Returns true if there are no conditions.
+    pub fn is_empty(&self) -> bool {
+        self.full_name_regex.is_none()
+            && self.entity_name_regex.is_none()
+            && self.tel.is_none()
+            && self.email.is_none()
+            && self.text_regex.is_none()
+    }
+
+    /// Returns true if a contact satisfies all conditions. A full name regex
+    /// alone excludes entities and an entity name regex alone excludes
+    /// persons.
+    pub fn is_match(&self, contact: &Contact) -> bool {
+        self.matches(
+            contact.full_name(),
+            contact.entity_name(),
+            contact.tels.values(),
+            contact
+                .emails
+                .values()
+                .map(|address| email::normalize(address)),
+            contact.labels.values(),
+        )
+    }
+
+    /// Returns true if a contact with given names, telephone numbers,
+    /// normalized e-mail addresses and label values satisfies all
+    /// conditions, see `is_match()`.
+    pub fn matches<'t, T, E, L>(
+        &self,
+        full_name: Option<&str>,
+        entity_name: Option<&str>,
+        tels: T,
+        emails: E,
+        labels: L,
+    ) -> bool
+    where
+        T: IntoIterator<Item = &'t Tel>,
+        E: IntoIterator + Clone,
+        E::Item: AsRef<str>,
+        L: IntoIterator,
+        L::Item: AsRef<str>,
+    {
+        if let Some(full_name_regex) = self.full_name_regex {
+            match full_name {
+                Some(full_name) => {
+                    if !full_name_regex.is_match(full_name) {
+                        return false;
+                    }
+                }
+                None => {
+                    if self.entity_name_regex.is_none() {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        if let Some(entity_name_regex) = self.entity_name_regex {
+            match entity_name {
+                Some(entity_name) => {
+                    if !entity_name_regex.is_match(entity_name) {
+                        return false;
+                    }
+                }
+                None => {
+                    if self.full_name_regex.is_none() {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        if let Some(tel) = self.tel {
+            if !tels.into_iter().any(|other| other.matches(tel)) {
+                return false;
+            }
+        }
+
+        if let Some(address) = self.email {
+            if !emails
+                .clone()
+                .into_iter()
+                .any(|other| other.as_ref() == address)
+            {
+                return false;
+            }
+        }
+
+        if let Some(text_regex) = self.text_regex {
+            let names = full_name.into_iter().chain(entity_name);
+            if !names.into_iter().any(|name| text_regex.is_match(name))
+                && !emails
+                    .into_iter()
+                    .any(|other| text_regex.is_match(other.as_ref()))
+                && !labels
+                    .into_iter()
+                    .any(|value| text_regex.is_match(value.as_ref()))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StorageKind {
+    /// All contacts and groups are stored in a single file, see
+    /// `SingleFile`.
+    File,
+    /// Each contact is stored in its own file, see `ContactFiles`.
+    Directory,
+    /// Each contact is stored in its own vCard file, see `Vdir`.
+    Vdir,
+    /// Contacts are stored in an SQLite database, see `Sqlite`.
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "file" => Ok(StorageKind::File),
+            "directory" => Ok(StorageKind::Directory),
+            "vdir" => Ok(StorageKind::Vdir),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(StorageKind::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => bail!("SQLite storage is not available, con-rs was built without it."),
+            _ => bail!("Unknown storage: {}", value),
+        }
+    }
+}
+
+impl StorageKind {
+    /// Name of the storage used in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageKind::File => "file",
+            StorageKind::Directory => "directory",
+            StorageKind::Vdir => "vdir",
+            #[cfg(feature = "sqlite")]
+            StorageKind::Sqlite => "sqlite",
+        }
+    }
+
+    pub fn open(&self, config: &Config) -> Result<Box<dyn ContactStore>> {
+        Ok(match self {
+            StorageKind::File => Box::new(SingleFile),
+            StorageKind::Directory => Box::new(ContactFiles),
+            StorageKind::Vdir => Box::new(Vdir::new(config.vdir()?, config.default_country()?)),
+            #[cfg(feature = "sqlite")]
+            StorageKind::Sqlite => {
+                Box::new(Sqlite::new(Contacts::get_contacts_dir()?.join(SQLITE_FILE)))
+            }
+        })
+    }
+}
+
+/// Append `--` and pathspecs matching files of all storages to git
+/// arguments.
+pub fn with_pathspecs<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(GIT_PATHSPECS);
+    args
+}
+
+/// Open the storage selected in the configuration.
+pub fn open() -> Result<Box<dyn ContactStore>> {
+    let config = Config::load()?;
+    config.storage()?.open(&config)
+}
+
+/// All contacts and groups stored in `contacts.json`.
+pub struct SingleFile;
+
+impl ContactStore for SingleFile {
+    fn exists(&self) -> Result<bool> {
+        Ok(Contacts::get_contacts_file(false)?.exists())
+    }
+
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)> {
+        Contacts::load_from_path_lenient(Contacts::get_contacts_file(false)?)
+    }
+
+    fn save(&self, contacts: &mut Contacts) -> Result<()> {
+        contacts.save_to_path(Contacts::get_contacts_file(true)?)
+    }
+
+    fn load_matching(&self, query: &Query) -> Result<(Contacts, Vec<ContactError>)> {
+        let path = Contacts::get_contacts_file(false)?;
+        // The search index is not encrypted, so encrypted contacts are never
+        // indexed.
+        if encryption::is_encrypted_file(&path) {
+            return self.load();
+        }
+        index::load_matching(self, std::slice::from_ref(&path), query, |positions| {
+            let content = fs::read_to_string(&path)
+                .context("Could not load contacts. Make sure contacts are initialized.")?;
+            let raw: RawContacts = serde_json::from_str(&content)
+                .with_context(|| format!("Error while loading {}.", path.display()))?;
+
+            let mut contacts = Vec::new();
+            for &position in positions {
+                let contact = raw
+                    .contacts
+                    .get(position)
+                    .context("Search index does not match contacts.")?;
+                let contact = serde_json::from_str(contact.get())
+                    .with_context(|| format!("Error while loading {}.", path.display()))?;
+                contacts.push(contact);
+            }
+            Ok(Contacts::from_parts(contacts, raw.groups))
+        })
+    }
+
+    fn backup(&self) -> Result<Vec<PathBuf>> {
+        Ok(vec![backup(&Contacts::get_contacts_file(false)?)?])
+    }
+
+    fn contact_path(&self, _id: &str) -> Result<String> {
+        Ok(CONTACTS_FILE.to_owned())
+    }
+}
+
+/// Each contact stored in its own file `contacts/<ID>.json` and all groups
+/// stored in `groups.json`. Unlike `SingleFile`, changes of different
+/// contacts never touch the same file.
+pub struct ContactFiles;
+
+impl ContactFiles {
+    fn contacts_dir() -> Result<PathBuf> {
+        Ok(Contacts::get_contacts_dir()?.join(CONTACTS_DIR))
+    }
+
+    /// Return paths of all contact files sorted by name.
+    fn contact_files(dir: &Path) -> Result<Vec<PathBuf>> {
+        list_files(dir, "json")
+    }
+
+    /// Save a contact to its file. Modification time is updated if the
+    /// contact differs from the stored one.
+    fn save_contact(path: &Path, contact: &mut Contact) -> Result<()> {
+        let previous = fs::read_to_string(path).ok();
+        if let Some(previous) = previous
+            .as_ref()
+            .and_then(|previous| serde_json::from_str(previous).ok())
+        {
+            contact.touch_if_changed(&previous);
+        }
+        write_json(path, contact, previous.as_deref())
+    }
+}
+
+impl ContactStore for ContactFiles {
+    fn exists(&self) -> Result<bool> {
+        Ok(Self::contacts_dir()?.is_dir())
+    }
+
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)> {
+        let mut contacts: Vec<Contact> = Vec::new();
+        for path in Self::contact_files(&Self::contacts_dir()?)? {
+            contacts.push(read_json(&path)?);
+        }
+
+        Ok(Contacts::from_parts(contacts, load_groups()?))
+    }
+
+    fn save(&self, contacts: &mut Contacts) -> Result<()> {
+        let dir = Self::contacts_dir()?;
+        fs::create_dir_all(&dir).with_context(|| {
+            format!(
+                "Error during creation of directory with contacts: {}",
+                dir.display()
+            )
+        })?;
+
+        contacts.sort();
+
+        let mut stale: HashSet<PathBuf> = Self::contact_files(&dir)?.into_iter().collect();
+        for contact in contacts.contacts_mut() {
+            let path = contact_file(&dir, contact.id(), "json")?;
+            stale.remove(&path);
+
+            Self::save_contact(&path, contact)?;
+        }
+
+        for path in stale {
+            fs::remove_file(&path)
+                .with_context(|| format!("Could not remove {}.", path.display()))?;
+        }
+
+        save_groups(contacts.groups())
+    }
+
+    fn backup(&self) -> Result<Vec<PathBuf>> {
+        let mut backups = vec![backup(&Self::contacts_dir()?)?];
+        backups.extend(backup_groups()?);
+        Ok(backups)
+    }
+
+    fn contact_path(&self, id: &str) -> Result<String> {
+        Ok(format!("{}/{}.json", CONTACTS_DIR, id))
+    }
+
+    fn load_matching(&self, query: &Query) -> Result<(Contacts, Vec<ContactError>)> {
+        let paths = Self::contact_files(&Self::contacts_dir()?)?;
+        index::load_matching(self, &paths, query, |positions| {
+            let mut contacts = Vec::new();
+            for &position in positions {
+                let path = paths
+                    .get(position)
+                    .context("Search index does not match contacts.")?;
+                contacts.push(read_json(path)?);
+            }
+            Ok(Contacts::from_parts(contacts, load_groups()?))
+        })
+    }
+
+    fn upsert(&self, mut contact: Contact) -> Result<()> {
+        if let Some((field, message)) = contact.validate() {
+            bail!("Invalid contact ({}): {}", field, message);
+        }
+
+        let path = contact_file(&Self::contacts_dir()?, contact.id(), "json")?;
+        Self::save_contact(&path, &mut contact)
+    }
+}
+
+/// Return paths of all files with a given extension in a directory sorted by
+/// name. Hidden files are skipped.
+pub fn list_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let entries = fs::read_dir(dir)
+        .context("Could not load contacts. Make sure contacts are initialized.")?;
+    for entry in entries {
+        let path = entry.context("Could not load contacts.")?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if !hidden && path.extension().is_some_and(|other| other == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Path of the file with a contact in a directory with one file per contact.
+pub fn contact_file(dir: &Path, id: &str, extension: &str) -> Result<PathBuf> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        bail!("Contact ID {} cannot be used as a file name.", id);
+    }
+    Ok(dir.join(format!("{}.{}", id, extension)))
+}
+
+/// Load groups stored separately from contacts in `groups.json`. No groups
+/// are returned if the file does not exist.
+pub fn load_groups() -> Result<Vec<Group>> {
+    let groups_file = Contacts::get_contacts_dir()?.join(GROUPS_FILE);
+    if groups_file.exists() {
+        read_json(&groups_file)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Save groups to `groups.json`, see `load_groups()`. The file is removed if
+/// there are no groups.
+pub fn save_groups(groups: &[Group]) -> Result<()> {
+    let dir = Contacts::get_contacts_dir()?;
+    let groups_file = dir.join(GROUPS_FILE);
+    let previous = fs::read_to_string(&groups_file).ok();
+    if !groups.is_empty() {
+        fs::create_dir_all(&dir).with_context(|| {
+            format!(
+                "Error during creation of directory with contacts: {}",
+                dir.display()
+            )
+        })?;
+        write_json(&groups_file, groups, previous.as_deref())?;
+    } else if previous.is_some() {
+        fs::remove_file(&groups_file).context("Could not remove groups file.")?;
+    }
+    Ok(())
+}
+
+/// Back up `groups.json` if it exists, see `backup()`.
+pub fn backup_groups() -> Result<Option<PathBuf>> {
+    let groups_file = Contacts::get_contacts_dir()?.join(GROUPS_FILE);
+    if groups_file.exists() {
+        Ok(Some(backup(&groups_file)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Format a serialized stored file (a contacts file, a file with a single
+/// contact or a file with groups) the same way the storages format it.
+/// Modification times are kept intact.
+pub fn format_file(file: Value) -> Result<String> {
+    let content = match file {
+        Value::Array(_) => {
+            let groups: Vec<Group> = serde_json::from_value(file).context("Invalid groups.")?;
+            serde_json::to_string_pretty(&groups)?
+        }
+        _ if file.get("contacts").is_some() => {
+            let mut contacts: Contacts =
+                serde_json::from_value(file).context("Invalid contacts.")?;
+            contacts.sort();
+            serde_json::to_string_pretty(&contacts)?
+        }
+        _ => {
+            let contact: Contact = serde_json::from_value(file).context("Invalid contact.")?;
+            serde_json::to_string_pretty(&contact)?
+        }
+    };
+    Ok(content + "\n")
+}
+
+fn read_json<T>(path: &Path) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Could not load contacts from {}.", path.display()))?;
+    let mut deserializer = serde_json::Deserializer::from_str(&content);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+        anyhow!(
+            "Error while loading {} at {}: {}",
+            path.display(),
+            error.path(),
+            error.inner()
+        )
+    })
+}
+
+/// Write a value to a JSON file unless the file already has the same
+/// content.
+fn write_json<T>(path: &Path, value: &T, previous: Option<&str>) -> Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let mut content =
+        serde_json::to_string_pretty(value).context("Error while saving contacts.")?;
+    content.push('\n');
+    if previous != Some(content.as_str()) {
+        fs::write(path, content).context("Error while saving contacts.")?;
+    }
+    Ok(())
+}
+
+/// Rename a file or a directory to a unique name with a timestamp, e.g.
+/// `contacts.json.20200101120000.bak`.
+pub fn backup(path: &Path) -> Result<PathBuf> {
+    let backup_path = backup_path(path, path.parent().unwrap_or(path))?;
+    fs::rename(path, &backup_path)
+        .with_context(|| format!("Could not back up {}.", path.display()))?;
+    Ok(backup_path)
+}
+
+/// Copy a directory to a unique name with a timestamp in the directory with
+/// contacts, see `backup()`. The directory itself is kept in place, which is
+/// needed for directories shared with other programs, e.g. a vdir
+/// synchronized by vdirsyncer.
+pub fn backup_copy(dir: &Path) -> Result<PathBuf> {
+    let contacts_dir = Contacts::get_contacts_dir()?;
+    fs::create_dir_all(&contacts_dir)
+        .context("Error during creation of directory with contacts.")?;
+    let backup_path = backup_path(dir, &contacts_dir)?;
+    copy_dir(dir, &backup_path).with_context(|| format!("Could not back up {}.", dir.display()))?;
+    Ok(backup_path)
+}
+
+/// Unique path of a backup of a file or a directory in a given directory.
+fn backup_path(path: &Path, dir: &Path) -> Result<PathBuf> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => bail!("Cannot back up {}.", path.display()),
+    };
+    let timestamp = Local::now().format("%Y%m%d%H%M%S").to_string();
+
+    let mut backup_path = dir.join(format!("{}.{}.bak", name, timestamp));
+    let mut counter = 1;
+    while backup_path.exists() {
+        counter += 1;
+        backup_path = dir.join(format!("{}.{}-{}.bak", name, timestamp, counter));
+    }
+    Ok(backup_path)
+}
+
+fn copy_dir(source: &Path, target: &Path) -> io::Result<()> {
+    fs::create_dir(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let target = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    fn store() -> MemoryStore {
+        MemoryStore::with_contacts(json!({
+            "contacts": [
+                {
+                    "id": "john",
+                    "fullName": "John Doe",
+                    "entityName": null,
+                    "tels": {},
+                    "emails": {},
+                    "labels": {},
+                    "relations": [{"type": "spouse", "contact": "jane"}],
+                },
+                {
+                    "id": "jane",
+                    "fullName": "Jane Doe",
+                    "entityName": null,
+                    "tels": {},
+                    "emails": {},
+                    "labels": {},
+                    "relations": [{"type": "spouse", "contact": "john"}],
+                },
+            ],
+            "groups": [{"name": "family", "contacts": ["john", "jane"]}],
+        }))
+    }
+
+    #[test]
+    fn get_by_id() {
+        let store = store();
+        let contact = store.get("jane").unwrap().unwrap();
+        assert_eq!(contact.full_name(), Some("Jane Doe"));
+        assert!(store.get("nobody").unwrap().is_none());
+    }
+
+    #[test]
+    fn delete_by_id() {
+        let store = store();
+        assert!(store.delete("jane").unwrap());
+        assert!(!store.delete("jane").unwrap());
+
+        let contacts = store.load_valid().unwrap();
+        let ids: Vec<&str> = contacts.contacts().iter().map(Contact::id).collect();
+        assert_eq!(ids, ["john"]);
+        assert!(contacts.contacts()[0].relations().is_empty());
+        assert_eq!(contacts.groups()[0].contacts, ["john"]);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::contact::Contacts;
+use crate::encryption;
+use crate::git;
+use crate::storage;
+use crate::threeway::{self, Conflict};
+use anyhow::{bail, Context, Result};
+use dialoguer::{theme::ColorfulTheme, Select};
+use serde_json::Value;
+use std::fs;
+use std::io::{self, IsTerminal};
+
+/// Synchronize contacts with the upstream branch of the current git branch.
+/// Local changes are committed, remote changes are fetched and merged
+/// contact by contact and the result is pushed. The user is asked only about
+/// fields changed differently on both sides.
+pub fn sync() -> Result<()> {
+    commit_local_changes()?;
+
+    git::call(["fetch"])?;
+    let upstream = git::output(["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"])
+        .context("The current branch has no upstream branch to synchronize with.")?;
+    let upstream = upstream.trim();
+
+    if git::succeeds(["merge-base", "--is-ancestor", upstream, "HEAD"])? {
+        eprintln!("No remote changes.");
+    } else if git::succeeds(["merge-base", "--is-ancestor", "HEAD", upstream])? {
+        git::call(["merge", "--ff-only", upstream])?;
+    } else {
+        merge(upstream)?;
+    }
+
+    if git::succeeds(["merge-base", "--is-ancestor", "HEAD", upstream])? {
+        eprintln!("No local changes to push.");
+    } else {
+        git::call(["push"])?;
+    }
+
+    eprintln!("Contacts synchronized.");
+    Ok(())
+}
+
+/// Commit uncommitted changes of stored contacts.
+fn commit_local_changes() -> Result<()> {
+    let status = git::output(storage::with_pathspecs(&["status", "--porcelain"]))?;
+    if !status.trim().is_empty() {
+        git::call(storage::with_pathspecs(&["add", "--all"]))?;
+        git::call(storage::with_pathspecs(&[
+            "commit",
+            "--message",
+            "Update contacts",
+        ]))?;
+    }
+    Ok(())
+}
+
+/// Merge an upstream revision into the current branch and commit the result.
+/// Stored files changed on either side are merged contact by contact, other
+/// files are merged by git.
+fn merge(upstream: &str) -> Result<()> {
+    let base = git::output(["merge-base", "HEAD", upstream])?;
+    let base = base.trim();
+
+    let mut paths: Vec<String> = Vec::new();
+    for revision in ["HEAD", upstream] {
+        let changed = git::output(storage::with_pathspecs(&[
+            "diff",
+            "--name-only",
+            base,
+            revision,
+        ]))?;
+        for path in changed.lines() {
+            if !paths.iter().any(|other| other == path) {
+                paths.push(path.to_owned());
+            }
+        }
+    }
+
+    let mut merged = Vec::new();
+    for path in paths.iter() {
+        let (base_version, _) = load_version(base, path)?;
+        let (our_version, ours_encrypted) = load_version("HEAD", path)?;
+        let (their_version, theirs_encrypted) = load_version(upstream, path)?;
+        let value = threeway::merge_file(
+            base_version.as_ref(),
+            our_version.as_ref(),
+            their_version.as_ref(),
+            &mut prompt_conflict,
+        )?;
+        merged.push((path, value, ours_encrypted || theirs_encrypted));
+    }
+
+    // The merge fails if git cannot merge files itself. Files merged above
+    // are overwritten below, other conflicts are checked afterwards.
+    git::succeeds(["merge", "--no-ff", "--no-commit", upstream])?;
+    if !git::succeeds(["rev-parse", "--quiet", "--verify", "MERGE_HEAD"])? {
+        bail!("Could not start merging {}.", upstream);
+    }
+    let unmerged = git::output(["diff", "--name-only", "--diff-filter=U"])?;
+    if unmerged
+        .lines()
+        .any(|path| !paths.iter().any(|other| other == path))
+    {
+        bail!(
+            "Some files could not be merged. Resolve the conflicts and commit \
+             the merge with con-rs git -- commit."
+        );
+    }
+
+    let contacts_dir = Contacts::get_contacts_dir()?;
+    for (path, value, encrypted) in merged {
+        let path = contacts_dir.join(path);
+        match value {
+            Some(value) => {
+                let mut content = storage::format_file(value)?;
+                if encrypted {
+                    content = encryption::encrypt(&content)?;
+                }
+                fs::write(&path, content)
+                    .with_context(|| format!("Could not write {}.", path.display()))?
+            }
+            None if path.exists() => fs::remove_file(&path)
+                .with_context(|| format!("Could not remove {}.", path.display()))?,
+            None => (),
+        }
+    }
+    git::call(storage::with_pathspecs(&["add", "--all"]))?;
+
+    if let Err(error) = Contacts::load_from_home() {
+        let message = if git::succeeds(["merge", "--abort"])? {
+            "Merged contacts are not valid. The merge has been aborted."
+        } else {
+            "Merged contacts are not valid. Abort the merge with con-rs git -- merge --abort."
+        };
+        return Err(error.context(message));
+    }
+
+    git::call(["commit", "--no-edit"])
+}
+
+/// Load a stored file from a git revision and decrypt it if needed. Returns
+/// None if the file does not exist in the revision and true if the file is
+/// encrypted.
+fn load_version(revision: &str, path: &str) -> Result<(Option<Value>, bool)> {
+    let object = format!("{}:{}", revision, path);
+    if !git::succeeds(["cat-file", "-e", &object])? {
+        return Ok((None, false));
+    }
+
+    let content = git::output(["show", &object])?;
+    let encrypted = encryption::is_encrypted(&content);
+    let content = encryption::decrypt_if_encrypted(content)?;
+    let value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid {} in revision {}.", path, revision))?;
+    Ok((Some(value), encrypted))
+}
+
+fn prompt_conflict(conflict: &Conflict) -> Result<Option<Value>> {
+    let show = |value: Option<&Value>| match value {
+        Some(value) => value.to_string(),
+        None => String::from("<removed>"),
+    };
+
+    if !io::stdin().is_terminal() {
+        bail!(
+            "Conflicting changes of {} have to be resolved interactively.",
+            conflict.path
+        );
+    }
+
+    eprintln!("Conflicting changes of {}", conflict.path);
+    eprintln!("Original: {}", show(conflict.base));
+    let items = [
+        format!("Local: {}", show(conflict.ours)),
+        format!("Remote: {}", show(conflict.theirs)),
+    ];
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select version to keep")
+        .default(0)
+        .items(&items[..])
+        .interact()
+        .context("Conflict has not been resolved.")?;
+
+    Ok(match selection {
+        0 => conflict.ours.cloned(),
+        _ => conflict.theirs.cloned(),
+    })
+}
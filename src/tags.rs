@@ -0,0 +1,59 @@
+use crate::contact::Contacts;
+use crate::storage::ContactStore;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// Print all tags together with number of contacts tagged by them.
+pub fn list(store: &dyn ContactStore) -> Result<()> {
+    let contacts = store.load_valid()?;
+
+    for (tag, count) in count(&contacts) {
+        println!("{}\t{}", tag, count);
+    }
+
+    Ok(())
+}
+
+/// Return all tags ordered by name together with number of contacts tagged
+/// by them.
+fn count(contacts: &Contacts) -> BTreeMap<&str, usize> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for contact in contacts.contacts() {
+        for tag in contact.tags.iter() {
+            *counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+    use serde_json::json;
+
+    #[test]
+    fn tags_counted() {
+        let contact = |full_name: &str, tags: &[&str]| {
+            json!({
+                "fullName": full_name,
+                "entityName": null,
+                "tels": {},
+                "emails": {},
+                "labels": {},
+                "tags": tags,
+            })
+        };
+        let store = MemoryStore::with_contacts(json!({
+            "contacts": [
+                contact("John Doe", &["work", "project=alpha"]),
+                contact("Jane Doe", &["work", "family", "work"]),
+                contact("Alice Roe", &[]),
+            ],
+        }));
+
+        let contacts = store.load_valid().unwrap();
+        let counts: Vec<(&str, usize)> = count(&contacts).into_iter().collect();
+        assert_eq!(counts, [("family", 1), ("project=alpha", 1), ("work", 2)]);
+    }
+}
@@ -0,0 +1,486 @@
+use crate::contact::Contact;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+
+/// A value changed differently by both sides of a merge.
+pub struct Conflict<'a> {
+    /// Human readable location of the value, e.g. `John Doe: emails.work`.
+    pub path: String,
+    /// The value in the common ancestor or None if it did not exist.
+    pub base: Option<&'a Value>,
+    /// Our version of the value or None if it was removed.
+    pub ours: Option<&'a Value>,
+    /// Their version of the value or None if it was removed.
+    pub theirs: Option<&'a Value>,
+}
+
+/// Three-way merge of serialized contacts files. Contacts are matched by
+/// their IDs and groups by their names. Changes made by only one side are
+/// applied field by field, lists (e.g. tags) are merged as sets and the later
+/// modification time is used. Fields changed differently by both sides and
+/// contacts which would end up invalid are passed to `resolve` which returns
+/// the resolved value (None to remove the value).
+///
+/// # Arguments
+///
+/// * `base` - the common ancestor.
+/// * `ours` - our version.
+/// * `theirs` - their version.
+/// * `resolve` - conflict resolution function.
+pub fn merge<F>(base: &Value, ours: &Value, theirs: &Value, resolve: &mut F) -> Result<Value>
+where
+    F: FnMut(&Conflict) -> Result<Option<Value>>,
+{
+    let mut result = ours.as_object().cloned().unwrap_or_default();
+
+    let contacts = merge_keyed(
+        &base["contacts"],
+        &ours["contacts"],
+        &theirs["contacts"],
+        "id",
+        resolve,
+    )?;
+    result.insert(String::from("contacts"), contacts);
+
+    let groups = merge_keyed(
+        &base["groups"],
+        &ours["groups"],
+        &theirs["groups"],
+        "name",
+        resolve,
+    )?;
+    if groups.as_array().is_some_and(|groups| !groups.is_empty()) {
+        result.insert(String::from("groups"), groups);
+    } else {
+        result.remove("groups");
+    }
+
+    Ok(Value::Object(result))
+}
+
+/// Merge lists of objects matched by value of a key property. Objects are
+/// ordered as in our version followed by objects added only by them.
+fn merge_keyed<F>(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    key: &str,
+    resolve: &mut F,
+) -> Result<Value>
+where
+    F: FnMut(&Conflict) -> Result<Option<Value>>,
+{
+    let find = |list: &'_ Value, id: &str| -> Option<Value> {
+        list.as_array()?
+            .iter()
+            .find(|item| item[key].as_str() == Some(id))
+            .cloned()
+    };
+
+    let mut ids: Vec<&str> = Vec::new();
+    for list in [ours, theirs] {
+        for item in list.as_array().into_iter().flatten() {
+            if let Some(id) = item[key].as_str() {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for id in ids {
+        let base = find(base, id);
+        let ours = find(ours, id);
+        let theirs = find(theirs, id);
+
+        let merged = if key == "id" {
+            merge_contact(base.as_ref(), ours.as_ref(), theirs.as_ref(), resolve)?
+        } else {
+            let label = label(ours.as_ref().or(theirs.as_ref()), id);
+            merge_value(
+                &label,
+                "",
+                base.as_ref(),
+                ours.as_ref(),
+                theirs.as_ref(),
+                resolve,
+            )?
+        };
+        result.extend(merged);
+    }
+
+    Ok(Value::Array(result))
+}
+
+/// Three-way merge of a single serialized contact. None stands for a missing
+/// (e.g. deleted) contact.
+pub fn merge_contact<F>(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    resolve: &mut F,
+) -> Result<Option<Value>>
+where
+    F: FnMut(&Conflict) -> Result<Option<Value>>,
+{
+    let any = ours.or(theirs).or(base);
+    let id = any
+        .and_then(|contact| contact["id"].as_str())
+        .unwrap_or_default();
+    let label = label(any, id);
+
+    let merged = merge_value(&label, "", base, ours, theirs, resolve)?;
+    if merged.as_ref().is_none_or(is_valid_contact) {
+        return Ok(merged);
+    }
+
+    resolve(&Conflict {
+        path: format!("{} (the merged contact is not valid)", label),
+        base,
+        ours,
+        theirs,
+    })
+}
+
+/// Three-way merge of a stored file: a contacts file, a file with a single
+/// contact or a file with groups (see `storage`). None stands for a missing
+/// file.
+pub fn merge_file<F>(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    resolve: &mut F,
+) -> Result<Option<Value>>
+where
+    F: FnMut(&Conflict) -> Result<Option<Value>>,
+{
+    match ours.or(theirs).or(base) {
+        None => Ok(None),
+        Some(Value::Array(_)) => {
+            let wrap = |groups: Option<&Value>| json!({ "contacts": [], "groups": groups.cloned().unwrap_or_else(|| json!([])) });
+            let merged = merge(&wrap(base), &wrap(ours), &wrap(theirs), resolve)?;
+            Ok(merged.get("groups").cloned())
+        }
+        Some(file) if file.get("contacts").is_some() => {
+            let empty = json!({ "contacts": [] });
+            let merged = merge(
+                base.unwrap_or(&empty),
+                ours.unwrap_or(&empty),
+                theirs.unwrap_or(&empty),
+                resolve,
+            )?;
+            Ok(Some(merged))
+        }
+        Some(_) => merge_contact(base, ours, theirs, resolve),
+    }
+}
+
+/// Three-way merge of a single value.
+///
+/// # Arguments
+///
+/// * `label` - human readable name of the merged contact or group.
+/// * `field` - path of the merged value within the contact or group, empty
+///   for the whole contact or group.
+fn merge_value<F>(
+    label: &str,
+    field: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    resolve: &mut F,
+) -> Result<Option<Value>>
+where
+    F: FnMut(&Conflict) -> Result<Option<Value>>,
+{
+    if same(ours, theirs) || same(base, theirs) {
+        return Ok(ours.cloned());
+    }
+    if same(base, ours) {
+        return Ok(theirs.cloned());
+    }
+
+    match (ours, theirs) {
+        (Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            let base = base.and_then(Value::as_object);
+            let mut result = Map::new();
+
+            let theirs_only = theirs.keys().filter(|key| !ours.contains_key(*key));
+            for key in ours.keys().chain(theirs_only) {
+                let merged = if field.is_empty() && key == "modified" {
+                    latest(ours.get(key), theirs.get(key))
+                } else {
+                    let field = if field.is_empty() {
+                        key.to_owned()
+                    } else {
+                        format!("{}.{}", field, key)
+                    };
+                    merge_value(
+                        label,
+                        &field,
+                        base.and_then(|base| base.get(key)),
+                        ours.get(key),
+                        theirs.get(key),
+                        resolve,
+                    )?
+                };
+
+                if let Some(merged) = merged {
+                    result.insert(key.to_owned(), merged);
+                }
+            }
+
+            Ok(Some(Value::Object(result)))
+        }
+        (Some(Value::Array(ours)), Some(Value::Array(theirs))) => {
+            let empty = Vec::new();
+            let base = base.and_then(Value::as_array).unwrap_or(&empty);
+            Ok(Some(Value::Array(merge_sets(base, ours, theirs))))
+        }
+        _ => {
+            let path = if field.is_empty() {
+                label.to_owned()
+            } else {
+                format!("{}: {}", label, field)
+            };
+            resolve(&Conflict {
+                path,
+                base,
+                ours,
+                theirs,
+            })
+        }
+    }
+}
+
+/// Returns true if both values are equal including order of object
+/// properties. Order of map items is significant, e.g. the first e-mail
+/// address is the preferred one.
+fn same(a: Option<&Value>, b: Option<&Value>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|((a_key, a), (b_key, b))| a_key == b_key && same(Some(a), Some(b)))
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(Some(a), Some(b)))
+        }
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Three-way merge of lists treated as sets. An item is kept if both sides
+/// kept it or if one side added it.
+fn merge_sets(base: &[Value], ours: &[Value], theirs: &[Value]) -> Vec<Value> {
+    let mut result: Vec<Value> = Vec::new();
+
+    for item in ours.iter().chain(theirs.iter()) {
+        if result.contains(item) {
+            continue;
+        }
+
+        let keep = if base.contains(item) {
+            ours.contains(item) && theirs.contains(item)
+        } else {
+            true
+        };
+        if keep {
+            result.push(item.clone());
+        }
+    }
+
+    result
+}
+
+/// Return the later of two serialized times.
+fn latest(ours: Option<&Value>, theirs: Option<&Value>) -> Option<Value> {
+    let parse = |value: Option<&Value>| -> Option<DateTime<Utc>> { value?.as_str()?.parse().ok() };
+
+    if parse(theirs) > parse(ours) {
+        theirs.cloned()
+    } else {
+        ours.or(theirs).cloned()
+    }
+}
+
+/// Human readable name of a serialized contact or group.
+fn label(item: Option<&Value>, id: &str) -> String {
+    ["fullName", "entityName", "name"]
+        .iter()
+        .find_map(|key| item?[*key].as_str())
+        .unwrap_or(id)
+        .to_owned()
+}
+
+fn is_valid_contact(contact: &Value) -> bool {
+    match serde_json::from_value::<Contact>(contact.clone()) {
+        Ok(contact) => contact.validate().is_none(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+
+    fn contact(id: &str, full_name: &str) -> Value {
+        json!({
+            "id": id,
+            "fullName": full_name,
+            "entityName": null,
+            "tels": {},
+            "emails": {},
+            "labels": {},
+        })
+    }
+
+    fn no_conflicts(conflict: &Conflict) -> Result<Option<Value>> {
+        bail!("Unexpected conflict: {}", conflict.path)
+    }
+
+    #[test]
+    fn fields_changed_by_each_side() {
+        let base = contact("a1", "John Doe");
+        let mut ours = base.clone();
+        ours["emails"]["work"] = json!("jd@example.com");
+        let mut theirs = base.clone();
+        theirs["fullName"] = json!("John Roe");
+        theirs["labels"]["nick"] = json!("jd");
+
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&theirs), &mut no_conflicts)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged["fullName"], "John Roe");
+        assert_eq!(merged["emails"]["work"], "jd@example.com");
+        assert_eq!(merged["labels"]["nick"], "jd");
+    }
+
+    #[test]
+    fn reordered_map_is_a_change() {
+        let mut base = contact("a1", "John Doe");
+        base["emails"] = json!({"home": "john@example.com", "work": "jd@example.com"});
+        let mut ours = base.clone();
+        ours["emails"] = json!({"work": "jd@example.com", "home": "john@example.com"});
+
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&base), &mut no_conflicts)
+            .unwrap()
+            .unwrap();
+        let keys: Vec<&String> = merged["emails"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["work", "home"]);
+    }
+
+    #[test]
+    fn sets_merged() {
+        let mut base = contact("a1", "John Doe");
+        base["tags"] = json!(["family", "work"]);
+        let mut ours = base.clone();
+        ours["tags"] = json!(["family", "work", "golf"]);
+        let mut theirs = base.clone();
+        theirs["tags"] = json!(["family"]);
+
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&theirs), &mut no_conflicts)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged["tags"], json!(["family", "golf"]));
+    }
+
+    #[test]
+    fn later_modification_time() {
+        let base = contact("a1", "John Doe");
+        let mut ours = base.clone();
+        ours["modified"] = json!("2021-01-01T00:00:00Z");
+        ours["labels"]["nick"] = json!("jd");
+        let mut theirs = base.clone();
+        theirs["modified"] = json!("2022-01-01T00:00:00Z");
+        theirs["labels"]["note"] = json!("golf");
+
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&theirs), &mut no_conflicts)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged["modified"], "2022-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn conflicting_field() {
+        let base = contact("a1", "John Doe");
+        let mut ours = base.clone();
+        ours["labels"]["nick"] = json!("jd");
+        let mut theirs = base.clone();
+        theirs["labels"]["nick"] = json!("johnny");
+
+        let mut conflicts = Vec::new();
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&theirs), &mut |conflict| {
+            conflicts.push(conflict.path.clone());
+            assert_eq!(conflict.base, None);
+            Ok(conflict.theirs.cloned())
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(conflicts, ["John Doe: labels.nick"]);
+        assert_eq!(merged["labels"]["nick"], "johnny");
+    }
+
+    #[test]
+    fn invalid_merged_contact() {
+        let base = contact("a1", "John Doe");
+        let mut ours = base.clone();
+        ours["fullName"] = Value::Null;
+        ours["entityName"] = json!("Acme");
+        let mut theirs = base.clone();
+        theirs["organization"] = json!("Acme");
+
+        let mut conflicts = 0;
+        let merged = merge_contact(Some(&base), Some(&ours), Some(&theirs), &mut |_| {
+            conflicts += 1;
+            Ok(Some(ours.clone()))
+        })
+        .unwrap();
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, Some(ours.clone()));
+    }
+
+    #[test]
+    fn contacts_added_and_removed() {
+        let john = contact("a1", "John Doe");
+        let jane = contact("b2", "Jane Roe");
+        let alice = contact("c3", "Alice Smith");
+        let base = json!({"contacts": [john, jane]});
+        let ours = json!({"contacts": [john, jane, alice]});
+        let theirs =
+            json!({"contacts": [jane], "groups": [{"name": "friends", "contacts": ["b2"]}]});
+
+        let merged = merge(&base, &ours, &theirs, &mut no_conflicts).unwrap();
+        let ids: Vec<&str> = merged["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|contact| contact["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["b2", "c3"]);
+        assert_eq!(merged["groups"][0]["name"], "friends");
+    }
+
+    #[test]
+    fn removed_and_changed_contact() {
+        let base = contact("a1", "John Doe");
+        let mut theirs = base.clone();
+        theirs["labels"]["nick"] = json!("jd");
+
+        let mut conflicts = 0;
+        let merged = merge_contact(Some(&base), None, Some(&theirs), &mut |conflict| {
+            conflicts += 1;
+            assert!(conflict.ours.is_none());
+            Ok(None)
+        })
+        .unwrap();
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, None);
+    }
+}
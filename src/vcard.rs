@@ -0,0 +1,872 @@
+use crate::contact::{self, Contact};
+use crate::email;
+use crate::phone::Tel;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use phonenumber::country;
+
+/// vCard properties which are fully represented by a contact, see
+/// `to_contact()` and `write()`. `N` is represented only partially.
+const MAPPED_PROPERTIES: [&str; 17] = [
+    "VERSION",
+    "UID",
+    "FN",
+    "N",
+    "KIND",
+    "X-ABSHOWAS",
+    "ORG",
+    "TITLE",
+    "TEL",
+    "EMAIL",
+    "CATEGORIES",
+    "NICKNAME",
+    "NOTE",
+    "REV",
+    "X-CON-RS-CREATED",
+    "X-CON-RS-LABEL",
+    "X-CON-RS-RELATION",
+];
+/// Format of times, e.g. `REV:20200101T120000Z`.
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// A content line of a vCard (RFC 6350), e.g. `TEL;TYPE=work:+420603123456`.
+pub struct Property {
+    /// Group of the property, e.g. `item1` of `item1.EMAIL:...`.
+    pub group: Option<String>,
+    /// Uppercased property name without a group.
+    pub name: String,
+    /// Parameters with uppercased names, e.g. `TYPE` -> `work`.
+    pub params: Vec<(String, String)>,
+    /// Raw (escaped) value of the property.
+    pub value: String,
+    /// The whole unfolded content line.
+    pub line: String,
+}
+
+impl Property {
+    /// Values of all `TYPE` parameters, lowercased.
+    fn types(&self) -> Vec<String> {
+        self.params
+            .iter()
+            .filter(|(name, _)| name == "TYPE")
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    /// Value of the first parameter with a given (uppercased) name.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns true if the property is marked as preferred, either with
+    /// `PREF` parameter (vCard 4.0) or with `TYPE=pref` (vCard 3.0).
+    fn is_preferred(&self) -> bool {
+        self.params.iter().any(|(name, _)| name == "PREF")
+            || self.types().iter().any(|kind| kind == "pref")
+    }
+}
+
+/// Parse vCards from a string. Returns properties of each vCard.
+pub fn parse(content: &str) -> Result<Vec<Vec<Property>>> {
+    let mut cards = Vec::new();
+    let mut card: Option<Vec<Property>> = None;
+
+    for line in unfold(content) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let property = parse_line(&line)?;
+        match (property.name.as_str(), card.as_mut()) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VCARD") => {
+                card = Some(Vec::new());
+            }
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("VCARD") => {
+                cards.extend(card.take());
+            }
+            (_, Some(properties)) => properties.push(property),
+            (_, None) => bail!("Unexpected vCard line outside of BEGIN:VCARD: {}", line),
+        }
+    }
+
+    if card.is_some() {
+        bail!("vCard is missing END:VCARD.");
+    }
+    Ok(cards)
+}
+
+/// Convert properties of a vCard to a contact. Properties which cannot be
+/// represented by a contact are ignored.
+///
+/// # Arguments
+///
+/// * `country` - country used for telephone numbers without an
+///   international calling code.
+pub fn to_contact(properties: &[Property], country: Option<country::Id>) -> Result<Contact> {
+    let find = |name: &str| {
+        properties
+            .iter()
+            .find(|property| property.name == name)
+            .map(|property| unescape(&property.value))
+    };
+    let organization = properties
+        .iter()
+        .find(|property| property.name == "ORG")
+        .and_then(|property| split_components(&property.value).into_iter().next())
+        .filter(|organization| !organization.is_empty());
+
+    let is_entity = find("KIND").is_some_and(|kind| kind.eq_ignore_ascii_case("org"))
+        || find("X-ABSHOWAS").is_some_and(|show_as| show_as.eq_ignore_ascii_case("company"));
+    let name = find("FN")
+        .filter(|name| !name.trim().is_empty())
+        .or_else(|| {
+            let components = split_components(&properties.iter().find(|p| p.name == "N")?.value);
+            // N is family;given;additional;prefixes;suffixes
+            let name: Vec<&str> = [3, 1, 2, 0, 4]
+                .iter()
+                .filter_map(|&index| components.get(index))
+                .map(|component| component.trim())
+                .filter(|component| !component.is_empty())
+                .collect();
+            Some(name.join(" ")).filter(|name| !name.is_empty())
+        });
+
+    let mut contact = match (name, is_entity) {
+        (Some(name), false) => {
+            let mut contact = Contact::with_full_name(name);
+            contact.set_organization(organization)?;
+            contact.set_title(find("TITLE"))?;
+            contact
+        }
+        (Some(name), true) => Contact::with_entity_name(name),
+        (None, _) => match organization {
+            Some(organization) => Contact::with_entity_name(organization),
+            None => bail!("vCard without a name."),
+        },
+    };
+
+    if let Some(uid) = find("UID") {
+        contact.set_id(uid.trim_start_matches("urn:uuid:").to_owned());
+    }
+    if let Some(created) = find("X-CON-RS-CREATED") {
+        contact.set_created(Some(parse_time(&created)?));
+    }
+    if let Some(modified) = find("REV") {
+        contact.set_modified(Some(parse_time(&modified)?));
+    }
+
+    for property in properties.iter() {
+        match property.name.as_str() {
+            "TEL" => {
+                let key = map_key(property, "tel");
+                let tel = Tel::parse(unescape(&property.value), country);
+                let key = contact::insert_unique(&mut contact.tels, &key, tel);
+                if property.is_preferred() {
+                    contact::set_preferred(&mut contact.tels, &key);
+                }
+            }
+            "EMAIL" => {
+                let key = map_key(property, "email");
+                let address = email::normalize(&unescape(&property.value));
+                let key = contact::insert_unique(&mut contact.emails, &key, address);
+                if property.is_preferred() {
+                    contact::set_preferred(&mut contact.emails, &key);
+                }
+            }
+            "CATEGORIES" => {
+                for tag in split_list(&property.value) {
+                    if !tag.trim().is_empty() {
+                        contact.tags.insert(tag.trim().to_owned());
+                    }
+                }
+            }
+            "NICKNAME" => {
+                for nick in split_list(&property.value) {
+                    if !nick.trim().is_empty() {
+                        contact::insert_unique(&mut contact.labels, "nick", nick.trim().to_owned());
+                    }
+                }
+            }
+            "NOTE" => {
+                let note = unescape(&property.value);
+                contact::insert_unique(&mut contact.labels, "note", note);
+            }
+            "X-CON-RS-LABEL" => {
+                let key = property.param("X-NAME").unwrap_or("label");
+                contact::insert_unique(&mut contact.labels, key, unescape(&property.value));
+            }
+            "X-CON-RS-RELATION" => {
+                let kind = property.param("X-TYPE").unwrap_or("related");
+                contact.add_relation(kind.to_owned(), unescape(&property.value), None)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(contact)
+}
+
+/// Serialize a contact to a vCard. Properties of `previous` (the vCard the
+/// contact has been loaded from, if any) which cannot be represented by a
+/// contact are kept unchanged, so that data of other applications is not
+/// lost. Original lines of mapped properties whose values have not changed
+/// are kept too, including their groups and parameters. New vCards are
+/// written in version 3.0.
+pub fn write(contact: &Contact, previous: &[Property]) -> String {
+    let find = |name: &str| previous.iter().find(|property| property.name == name);
+    let version = find("VERSION")
+        .map(|property| property.value.trim().to_owned())
+        .unwrap_or_else(|| String::from("3.0"));
+    let is_v4 = version.starts_with('4');
+    let name = contact.name().unwrap_or_default();
+
+    let mut writer = Writer::new(previous);
+    writer.lines.push(String::from("BEGIN:VCARD"));
+    writer.push("VERSION", format!(":{}", version), |_| true);
+    writer.push("UID", format!(":{}", escape(contact.id())), |property| {
+        unescape(&property.value).trim_start_matches("urn:uuid:") == contact.id()
+    });
+    writer.push("FN", format!(":{}", escape(name)), |property| {
+        unescape(&property.value) == name
+    });
+
+    // N is structured, keep it unless the name has changed.
+    let previous_name = find("FN").map(|property| unescape(&property.value));
+    let n = match contact.full_name() {
+        Some(full_name) => {
+            let mut words: Vec<&str> = full_name.split_whitespace().collect();
+            let family = words.pop().unwrap_or_default();
+            format!(":{};{};;;", escape(family), escape(&words.join(" ")))
+        }
+        None => String::from(":;;;;"),
+    };
+    writer.push("N", n, |_| previous_name.as_deref() == Some(name));
+
+    if let Some(entity_name) = contact.entity_name() {
+        if is_v4 {
+            writer.push("KIND", String::from(":org"), |property| {
+                property.value.eq_ignore_ascii_case("org")
+            });
+        } else {
+            writer.push("X-ABSHOWAS", String::from(":COMPANY"), |property| {
+                property.value.eq_ignore_ascii_case("company")
+            });
+        }
+        writer.push_org(entity_name);
+    }
+    if let Some(organization) = contact.organization() {
+        writer.push_org(organization);
+    }
+    if let Some(title) = contact.title() {
+        writer.push("TITLE", format!(":{}", escape(title)), |property| {
+            unescape(&property.value) == title
+        });
+    }
+
+    for (index, (key, tel)) in contact.tels.iter().enumerate() {
+        let params = type_params(key, "tel", index == 0, is_v4);
+        let line = format!("{}:{}", params, escape(tel.number()));
+        writer.push_typed("TEL", line, key, "tel", index == 0, |value| {
+            unescape(value) == tel.number()
+        });
+    }
+    for (index, (key, address)) in contact.emails.iter().enumerate() {
+        let params = type_params(key, "email", index == 0, is_v4);
+        let line = format!("{}:{}", params, escape(address));
+        writer.push_typed("EMAIL", line, key, "email", index == 0, |value| {
+            email::normalize(&unescape(value)) == *address
+        });
+    }
+
+    if !contact.tags.is_empty() {
+        let tags: Vec<String> = contact.tags.iter().map(|tag| escape(tag)).collect();
+        writer.push("CATEGORIES", format!(":{}", tags.join(",")), |property| {
+            let previous_tags: Vec<String> = split_list(&property.value)
+                .into_iter()
+                .map(|tag| tag.trim().to_owned())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            previous_tags.len() == contact.tags.len()
+                && previous_tags.iter().all(|tag| contact.tags.contains(tag))
+        });
+    }
+
+    let nicks: Vec<&str> = contact
+        .labels
+        .iter()
+        .filter(|(key, _)| base_key(key) == "nick")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    writer.push_nicks(&nicks);
+    for (key, value) in contact.labels.iter() {
+        match base_key(key) {
+            "nick" => (),
+            "note" => writer.push("NOTE", format!(":{}", escape(value)), |property| {
+                unescape(&property.value) == *value
+            }),
+            _ => writer.push(
+                "X-CON-RS-LABEL",
+                format!(";X-NAME={}:{}", param_value(key), escape(value)),
+                |property| {
+                    property.param("X-NAME") == Some(key.as_str())
+                        && unescape(&property.value) == *value
+                },
+            ),
+        }
+    }
+    for relation in contact.relations() {
+        writer.push(
+            "X-CON-RS-RELATION",
+            format!(
+                ";X-TYPE={}:{}",
+                param_value(&relation.kind),
+                escape(&relation.contact)
+            ),
+            |property| {
+                property.param("X-TYPE") == Some(relation.kind.as_str())
+                    && unescape(&property.value) == relation.contact
+            },
+        );
+    }
+
+    if let Some(created) = contact.created() {
+        let line = format!(":{}", created.format(TIME_FORMAT));
+        writer.push("X-CON-RS-CREATED", line, |property| {
+            parse_time(&property.value).is_ok_and(|time| time == created)
+        });
+    }
+    if let Some(modified) = contact.modified() {
+        let line = format!(":{}", modified.format(TIME_FORMAT));
+        writer.push("REV", line, |property| {
+            parse_time(&property.value).is_ok_and(|time| time == modified)
+        });
+    }
+
+    let mut lines = writer.finish();
+    lines.push(String::from("END:VCARD"));
+
+    let mut content = String::new();
+    for line in lines {
+        fold(&line, &mut content);
+    }
+    content
+}
+
+/// Content lines of a vCard being written, see `write()`.
+struct Writer<'a> {
+    previous: &'a [Property],
+    /// Properties of `previous` whose lines or groups have been reused.
+    used: Vec<bool>,
+    lines: Vec<String>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(previous: &'a [Property]) -> Self {
+        Self {
+            previous,
+            used: vec![false; previous.len()],
+            lines: Vec::new(),
+        }
+    }
+
+    /// Position of the first unused previous property with a given name
+    /// satisfying a condition.
+    fn position<F>(&self, name: &str, condition: F) -> Option<usize>
+    where
+        F: Fn(&Property) -> bool,
+    {
+        self.previous
+            .iter()
+            .enumerate()
+            .position(|(index, property)| {
+                !self.used[index] && property.name == name && condition(property)
+            })
+    }
+
+    /// Add a mapped property. The original line of a previous property with
+    /// the same name for which `same` returns true is added instead if there
+    /// is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - property name.
+    /// * `rest` - parameters and value of the new line, e.g. `;TYPE=work:...`.
+    /// * `same` - returns true if a previous property has the same value.
+    fn push<F>(&mut self, name: &str, rest: String, same: F)
+    where
+        F: Fn(&Property) -> bool,
+    {
+        self.push_related(name, rest, same, |_| true);
+    }
+
+    /// The same as `push()`, but if no previous property has the same value,
+    /// the group of the first previous property for which `related` returns
+    /// true is kept.
+    fn push_related<F, G>(&mut self, name: &str, rest: String, same: F, related: G)
+    where
+        F: Fn(&Property) -> bool,
+        G: Fn(&Property) -> bool,
+    {
+        if let Some(index) = self.position(name, same) {
+            self.used[index] = true;
+            self.lines.push(self.previous[index].line.clone());
+            return;
+        }
+
+        match self.position(name, related) {
+            Some(index) => {
+                self.used[index] = true;
+                let line = match &self.previous[index].group {
+                    Some(group) => format!("{}.{}{}", group, name, rest),
+                    None => format!("{}{}", name, rest),
+                };
+                self.lines.push(line);
+            }
+            None => self.lines.push(format!("{}{}", name, rest)),
+        }
+    }
+
+    /// Add a telephone number or an e-mail address with a given key, see
+    /// `map_key()`. A previous property is the same if it has the same key
+    /// and value and it is not marked as preferred unless the new one is
+    /// preferred too.
+    fn push_typed<F>(
+        &mut self,
+        name: &str,
+        rest: String,
+        key: &str,
+        default: &str,
+        preferred: bool,
+        same_value: F,
+    ) where
+        F: Fn(&str) -> bool,
+    {
+        let key = base_key(key);
+        self.push_related(
+            name,
+            rest,
+            |property| {
+                map_key(property, default) == key
+                    && same_value(&property.value)
+                    && (preferred || !property.is_preferred())
+            },
+            |property| map_key(property, default) == key,
+        );
+    }
+
+    /// Add an organization, i.e. the first component of `ORG`. Other
+    /// components (e.g. departments) of the previous `ORG` are kept.
+    fn push_org(&mut self, organization: &str) {
+        let units = self
+            .position("ORG", |_| true)
+            .and_then(|index| split_first_component(&self.previous[index].value))
+            .map(|(_, units)| format!(";{}", units))
+            .unwrap_or_default();
+        self.push(
+            "ORG",
+            format!(":{}{}", escape(organization), units),
+            |property| {
+                split_components(&property.value)
+                    .first()
+                    .is_some_and(|first| first == organization)
+            },
+        );
+    }
+
+    /// Add nicks. Previous `NICKNAME` properties with lists of nicks are kept
+    /// if the nicks have not changed, other nicks get a property each.
+    fn push_nicks(&mut self, nicks: &[&str]) {
+        let mut rest = nicks;
+        while !rest.is_empty() {
+            let previous = self.position("NICKNAME", |property| {
+                let values = property_nicks(property);
+                !values.is_empty()
+                    && rest.len() >= values.len()
+                    && values.iter().zip(rest.iter()).all(|(a, b)| a == b)
+            });
+            match previous {
+                Some(index) => {
+                    self.used[index] = true;
+                    self.lines.push(self.previous[index].line.clone());
+                    rest = &rest[property_nicks(&self.previous[index]).len()..];
+                }
+                None => {
+                    let nick = rest[0];
+                    self.push("NICKNAME", format!(":{}", escape(nick)), |property| {
+                        property_nicks(property) == [nick]
+                    });
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+
+    /// All lines including unmapped previous properties. Unmapped properties
+    /// in a group are dropped if the group belonged to a mapped property which
+    /// has been removed, e.g. a label (`item1.X-ABLABEL`) of a removed e-mail
+    /// address (`item1.EMAIL`).
+    fn finish(self) -> Vec<String> {
+        let group_of = |index: usize| self.previous[index].group.as_deref();
+        let is_mapped =
+            |index: usize| MAPPED_PROPERTIES.contains(&self.previous[index].name.as_str());
+        let kept_groups: Vec<&str> = (0..self.previous.len())
+            .filter(|&index| is_mapped(index) && self.used[index])
+            .filter_map(group_of)
+            .collect();
+        let removed_groups: Vec<&str> = (0..self.previous.len())
+            .filter(|&index| is_mapped(index) && !self.used[index])
+            .filter_map(group_of)
+            .filter(|group| !kept_groups.contains(group))
+            .collect();
+
+        let mut lines = self.lines.clone();
+        lines.extend(
+            (0..self.previous.len())
+                .filter(|&index| !is_mapped(index))
+                .filter(|&index| {
+                    group_of(index).is_none_or(|group| !removed_groups.contains(&group))
+                })
+                .map(|index| self.previous[index].line.clone()),
+        );
+        lines
+    }
+}
+
+/// Nicks of a `NICKNAME` property.
+fn property_nicks(property: &Property) -> Vec<String> {
+    split_list(&property.value)
+        .into_iter()
+        .map(|nick| nick.trim().to_owned())
+        .filter(|nick| !nick.is_empty())
+        .collect()
+}
+
+/// Key without a number appended by `contact::insert_unique()`, e.g. `work`
+/// for `work 2`.
+fn base_key(key: &str) -> &str {
+    match key.rsplit_once(' ') {
+        Some((base, number)) if number.parse::<u32>().is_ok() => base,
+        _ => key,
+    }
+}
+
+/// Parameters of a telephone number or an e-mail address with a given key,
+/// see `map_key()`.
+fn type_params(key: &str, default: &str, preferred: bool, is_v4: bool) -> String {
+    let mut types = Vec::new();
+    if key != default {
+        types.push(param_value(key));
+    }
+    if preferred && !is_v4 {
+        types.push(String::from("pref"));
+    }
+
+    let mut params = String::new();
+    if !types.is_empty() {
+        params.push_str(";TYPE=");
+        params.push_str(&types.join(","));
+    }
+    if preferred && is_v4 {
+        params.push_str(";PREF=1");
+    }
+    params
+}
+
+/// Key of a telephone number or an e-mail address in contact maps. The first
+/// type which is not a generic one (e.g. `voice` or `internet`) is used.
+fn map_key(property: &Property, default: &str) -> String {
+    property
+        .types()
+        .into_iter()
+        .find(|kind| !["pref", "voice", "internet", "x400"].contains(&kind.as_str()))
+        .unwrap_or_else(|| default.to_owned())
+}
+
+/// Join folded lines, see section 3.2 of RFC 6350.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (
+            line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+fn parse_line(line: &str) -> Result<Property> {
+    let mut quoted = false;
+    let colon = line
+        .char_indices()
+        .find(|&(_, character)| {
+            if character == '"' {
+                quoted = !quoted;
+            }
+            character == ':' && !quoted
+        })
+        .map(|(index, _)| index)
+        .with_context(|| format!("Invalid vCard line: {}", line))?;
+
+    let mut parts = line[..colon].split(';');
+    // Split an optional group, e.g. `item1.EMAIL`.
+    let name = parts.next().unwrap_or_default();
+    let (group, name) = match name.rsplit_once('.') {
+        Some((group, name)) => (Some(group.to_owned()), name),
+        None => (None, name),
+    };
+    let name = name.to_uppercase();
+
+    let params = parts
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => (name.to_uppercase(), value.trim_matches('"').to_owned()),
+            // vCard 2.1 parameters without a name, e.g. `TEL;WORK:...`.
+            None => (String::from("TYPE"), param.to_owned()),
+        })
+        .collect();
+
+    Ok(Property {
+        group,
+        name,
+        params,
+        value: line[colon + 1..].to_owned(),
+        line: line.to_owned(),
+    })
+}
+
+/// Append a content line folded to lines of at most 75 octets, see section
+/// 3.2 of RFC 6350.
+fn fold(line: &str, content: &mut String) {
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            content.push_str("\r\n ");
+            length = 1;
+        }
+        content.push(character);
+        length += character.len_utf8();
+    }
+    content.push_str("\r\n");
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let time = NaiveDateTime::parse_from_str(value, TIME_FORMAT)
+        .with_context(|| format!("Invalid vCard time: {}", value))?;
+    Ok(DateTime::from_naive_utc_and_offset(time, Utc))
+}
+
+/// Escape a text value, see section 3.4 of RFC 6350.
+fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '\\' | ',' | ';' => {
+                result.push('\\');
+                result.push(character);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            _ => result.push(character),
+        }
+    }
+    result
+}
+
+/// Quote a parameter value if needed, see section 3.3 of RFC 6350.
+fn param_value(value: &str) -> String {
+    let value = value.replace('"', "");
+    if value.contains([';', ':', ',']) {
+        format!("\"{}\"", value)
+    } else {
+        value
+    }
+}
+
+/// Unescape a text value, see section 3.4 of RFC 6350.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            result.push(character);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Split a structured value (e.g. `N` or `ORG`) to unescaped components.
+fn split_components(value: &str) -> Vec<String> {
+    split_escaped(value, ';')
+}
+
+/// Split a raw structured value to its first component and the rest of the
+/// value which is kept escaped. None is returned if there is just one
+/// component.
+fn split_first_component(value: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (index, character) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if character == '\\' {
+            escaped = true;
+        } else if character == ';' {
+            return Some((&value[..index], &value[index + 1..]));
+        }
+    }
+    None
+}
+
+/// Split a list value (e.g. `CATEGORIES`) to unescaped items.
+fn split_list(value: &str) -> Vec<String> {
+    split_escaped(value, ',')
+}
+
+fn split_escaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for character in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(character);
+            escaped = false;
+        } else if character == '\\' {
+            escaped = true;
+        } else if character == separator {
+            parts.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(character);
+        }
+    }
+    parts.push(unescape(&current));
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD: &str = "BEGIN:VCARD\r\n\
+        VERSION:3.0\r\n\
+        UID:3f2a9c1e-7b6d-4d3c-9a8b-0123456789ab\r\n\
+        FN:John Doe\r\n\
+        N:Doe;John;;;\r\n\
+        ORG:Acme;Sales\r\n\
+        NICKNAME:jd,johnny\r\n\
+        TEL;TYPE=cell,voice:+420603123456\r\n\
+        item1.EMAIL;TYPE=INTERNET:jd@example.com\r\n\
+        item1.X-ABLabel:Private\r\n\
+        X-UNKNOWN:kept\r\n\
+        END:VCARD\r\n";
+
+    fn load(content: &str) -> (Vec<Property>, Contact) {
+        let properties = parse(content).unwrap().remove(0);
+        let mut contact = to_contact(&properties, None).unwrap();
+        // The same as for vCards without times in a vdir.
+        contact.set_created(None);
+        contact.set_modified(None);
+        (properties, contact)
+    }
+
+    fn lines(content: &str) -> Vec<String> {
+        unfold(content)
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn nickname_list() {
+        let (_, contact) = load(CARD);
+        assert_eq!(contact.labels.get("nick").map(String::as_str), Some("jd"));
+        assert_eq!(
+            contact.labels.get("nick 2").map(String::as_str),
+            Some("johnny")
+        );
+        assert_eq!(contact.organization(), Some("Acme"));
+    }
+
+    #[test]
+    fn unchanged_round_trip() {
+        let (properties, contact) = load(CARD);
+        let written = write(&contact, &properties);
+        let written = lines(&written);
+        for line in lines(CARD) {
+            assert!(written.contains(&line), "{} missing in {:?}", line, written);
+        }
+        assert_eq!(written.len(), lines(CARD).len());
+    }
+
+    #[test]
+    fn changed_email_keeps_group() {
+        let (properties, mut contact) = load(CARD);
+        contact
+            .emails
+            .insert(String::from("email"), String::from("john@example.com"));
+        let written = lines(&write(&contact, &properties));
+        assert!(written.contains(&String::from("item1.EMAIL;TYPE=pref:john@example.com")));
+        assert!(written.contains(&String::from("item1.X-ABLabel:Private")));
+    }
+
+    #[test]
+    fn removed_email_drops_group() {
+        let (properties, mut contact) = load(CARD);
+        contact.emails.clear();
+        let written = lines(&write(&contact, &properties));
+        assert!(!written.iter().any(|line| line.starts_with("item1.")));
+        assert!(written.contains(&String::from("X-UNKNOWN:kept")));
+    }
+
+    #[test]
+    fn changed_organization_keeps_units() {
+        let (properties, mut contact) = load(CARD);
+        contact
+            .set_organization(Some(String::from("Acme Corp")))
+            .unwrap();
+        let written = lines(&write(&contact, &properties));
+        assert!(written.contains(&String::from("ORG:Acme Corp;Sales")));
+    }
+
+    #[test]
+    fn added_nick() {
+        let (properties, mut contact) = load(CARD);
+        contact
+            .labels
+            .insert(String::from("nick 3"), String::from("j, d"));
+        let written = lines(&write(&contact, &properties));
+        assert!(written.contains(&String::from("NICKNAME:jd,johnny")));
+        assert!(written.contains(&String::from("NICKNAME:j\\, d")));
+
+        let (_, reloaded) = load(&write(&contact, &properties));
+        assert_eq!(
+            reloaded.labels.get("nick 3").map(String::as_str),
+            Some("j, d")
+        );
+    }
+
+    #[test]
+    fn new_card() {
+        let (_, contact) = load(CARD);
+        let written = lines(&write(&contact, &[]));
+        assert!(written.contains(&String::from("TEL;TYPE=cell,pref:+420603123456")));
+        assert!(written.contains(&String::from("EMAIL;TYPE=pref:jd@example.com")));
+        assert!(written.contains(&String::from("NICKNAME:jd")));
+        assert!(written.contains(&String::from("NICKNAME:johnny")));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::contact::{Contact, ContactError, Contacts};
+use crate::storage::{self, ContactStore};
+use crate::vcard::{self, Property};
+use anyhow::{bail, Context, Result};
+use phonenumber::country;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Each contact stored in its own vCard file in a vdir, i.e. a directory with
+/// one `.vcf` file per contact as used by vdirsyncer and khard. vCard
+/// properties which cannot be represented by a contact are kept when a
+/// contact is saved and unchanged contacts are never rewritten. Groups cannot
+/// be represented by vCards and are stored in `groups.json` in the directory
+/// with contacts.
+pub struct Vdir {
+    path: PathBuf,
+    country: Option<country::Id>,
+}
+
+/// A contact loaded from a vCard file.
+struct Card {
+    path: PathBuf,
+    properties: Vec<Property>,
+    contact: Contact,
+}
+
+impl Vdir {
+    /// # Arguments
+    ///
+    /// * `path` - the vdir.
+    /// * `country` - country used for telephone numbers without an
+    ///   international calling code.
+    pub fn new(path: PathBuf, country: Option<country::Id>) -> Self {
+        Self { path, country }
+    }
+
+    /// Load all vCard files of the vdir.
+    fn read(&self) -> Result<Vec<Card>> {
+        let mut cards = Vec::new();
+        for path in storage::list_files(&self.path, "vcf")? {
+            cards.push(self.read_card(&path)?);
+        }
+        Ok(cards)
+    }
+
+    fn read_card(&self, path: &Path) -> Result<Card> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not load contacts from {}.", path.display()))?;
+        let mut cards = vcard::parse(&content)
+            .with_context(|| format!("Invalid vCard file {}.", path.display()))?;
+        if cards.len() != 1 {
+            bail!("{} does not contain exactly one vCard.", path.display());
+        }
+        let properties = cards.remove(0);
+
+        let mut contact = vcard::to_contact(&properties, self.country)
+            .with_context(|| format!("Invalid vCard file {}.", path.display()))?;
+        let has = |name: &str| properties.iter().any(|property| property.name == name);
+        // The file name identifies a vCard without UID in a vdir.
+        if !has("UID") {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            contact.set_id(stem.into_owned());
+        }
+        // Times of contacts unknown to con-rs must not change between loads.
+        if !has("X-CON-RS-CREATED") {
+            contact.set_created(None);
+        }
+        if !has("REV") {
+            contact.set_modified(None);
+        }
+
+        Ok(Card {
+            path: path.to_owned(),
+            properties,
+            contact,
+        })
+    }
+
+    /// Path of a new vCard file for a contact. A suffix is added if a file
+    /// with a different contact has the same name.
+    fn new_card_path(&self, id: &str) -> Result<PathBuf> {
+        let mut path = storage::contact_file(&self.path, id, "vcf")?;
+        let mut counter = 1;
+        while path.exists() {
+            counter += 1;
+            path = storage::contact_file(&self.path, &format!("{}-{}", id, counter), "vcf")?;
+        }
+        Ok(path)
+    }
+}
+
+impl ContactStore for Vdir {
+    fn exists(&self) -> Result<bool> {
+        Ok(self.path.is_dir() && !storage::list_files(&self.path, "vcf")?.is_empty())
+    }
+
+    fn load(&self) -> Result<(Contacts, Vec<ContactError>)> {
+        let contacts = self.read()?.into_iter().map(|card| card.contact).collect();
+        Ok(Contacts::from_parts(contacts, storage::load_groups()?))
+    }
+
+    fn save(&self, contacts: &mut Contacts) -> Result<()> {
+        fs::create_dir_all(&self.path).with_context(|| {
+            format!(
+                "Error during creation of directory with contacts: {}",
+                self.path.display()
+            )
+        })?;
+
+        let mut stored: HashMap<String, Card> = HashMap::new();
+        for card in self.read()? {
+            if let Some(other) = stored.get(card.contact.id()) {
+                bail!(
+                    "Duplicate vCard UID {} in {} and {}.",
+                    card.contact.id(),
+                    other.path.display(),
+                    card.path.display()
+                );
+            }
+            stored.insert(card.contact.id().to_owned(), card);
+        }
+
+        for contact in contacts.contacts_mut() {
+            let (path, content) = match stored.remove(contact.id()) {
+                Some(card) => {
+                    let previous = serde_json::to_value(&card.contact)?;
+                    if !contact.touch_if_changed(&previous) {
+                        continue;
+                    }
+                    (card.path, vcard::write(contact, &card.properties))
+                }
+                None => (
+                    self.new_card_path(contact.id())?,
+                    vcard::write(contact, &[]),
+                ),
+            };
+            fs::write(&path, content)
+                .with_context(|| format!("Could not write {}.", path.display()))?;
+        }
+
+        for card in stored.values() {
+            fs::remove_file(&card.path)
+                .with_context(|| format!("Could not remove {}.", card.path.display()))?;
+        }
+
+        storage::save_groups(contacts.groups())
+    }
+
+    fn backup(&self) -> Result<Vec<PathBuf>> {
+        // The vdir is usually synchronized by other programs, so it is
+        // copied rather than moved aside.
+        let mut backups = vec![storage::backup_copy(&self.path)?];
+        backups.extend(storage::backup_groups()?);
+        Ok(backups)
+    }
+
+    fn contact_path(&self, _id: &str) -> Result<String> {
+        bail!("Contacts in vdir storage are not tracked in the directory with contacts.");
+    }
+}
@@ -0,0 +1,110 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// A home directory with its own contacts, configuration and cache.
+struct Home {
+    home: PathBuf,
+}
+
+impl Home {
+    fn new(root: &Path) -> Self {
+        let home = root.join("home");
+        fs::create_dir_all(home.join("config").join("conn-rs")).unwrap();
+        Self { home }
+    }
+
+    fn key_file(&self) -> PathBuf {
+        self.home.join("keys").join("contacts.txt")
+    }
+
+    fn contacts_file(&self) -> PathBuf {
+        self.home.join("data").join("conn-rs").join("contacts.json")
+    }
+
+    fn index_file(&self) -> PathBuf {
+        self.home
+            .join("cache")
+            .join("conn-rs")
+            .join("search-index.json")
+    }
+
+    fn write_config(&self, config: Value) {
+        let path = self.home.join("config").join("conn-rs").join("config.json");
+        fs::write(path, config.to_string()).unwrap();
+    }
+
+    /// Run con-rs and return its standard output.
+    fn con_rs(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_con-rs"))
+            .env("HOME", &self.home)
+            .env("XDG_DATA_HOME", self.home.join("data"))
+            .env("XDG_CONFIG_HOME", self.home.join("config"))
+            .env("XDG_CACHE_HOME", self.home.join("cache"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "con-rs {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+#[test]
+fn encrypt_with_key_file() {
+    let root = std::env::temp_dir().join(format!("con-rs-encryption-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let home = Home::new(&root);
+    home.write_config(json!({ "encryptionKeyFile": home.key_file() }));
+
+    let initial = root.join("initial.json");
+    let contacts = json!({
+        "contacts": [{
+            "id": "a1",
+            "fullName": "John Doe",
+            "entityName": null,
+            "tels": {},
+            "emails": {"work": "jd@example.com"},
+            "labels": {},
+        }],
+    });
+    fs::write(&initial, contacts.to_string()).unwrap();
+    home.con_rs(&["init", "--from", initial.to_str().unwrap()]);
+
+    // Searching by name builds the plaintext search index.
+    assert!(home
+        .con_rs(&["search", "--name", "John", "print"])
+        .contains("John Doe"));
+    assert!(home.index_file().exists());
+
+    home.con_rs(&["encrypt"]);
+    assert!(home.key_file().exists());
+    let encrypted = fs::read_to_string(home.contacts_file()).unwrap();
+    assert!(encrypted.starts_with(ARMOR_BEGIN));
+    assert!(!encrypted.contains("John Doe"));
+    assert!(!home.index_file().exists());
+
+    // Encrypted contacts are loaded, saved encrypted again and not indexed.
+    assert!(home
+        .con_rs(&["search", "--name", "John", "print"])
+        .contains("John Doe"));
+    assert!(!home.index_file().exists());
+    home.con_rs(&["group", "create", "friends"]);
+    let encrypted = fs::read_to_string(home.contacts_file()).unwrap();
+    assert!(encrypted.starts_with(ARMOR_BEGIN));
+
+    home.con_rs(&["decrypt"]);
+    let decrypted: Value =
+        serde_json::from_str(&fs::read_to_string(home.contacts_file()).unwrap()).unwrap();
+    assert_eq!(decrypted["contacts"][0]["fullName"], "John Doe");
+    assert_eq!(decrypted["groups"][0]["name"], "friends");
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,136 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A home directory with contacts in a git repository.
+struct Home {
+    home: PathBuf,
+}
+
+impl Home {
+    fn new(root: &Path) -> Self {
+        let home = root.join("home");
+        fs::create_dir_all(home.join("config").join("conn-rs")).unwrap();
+        Self { home }
+    }
+
+    fn contacts_dir(&self) -> PathBuf {
+        self.home.join("data").join("conn-rs")
+    }
+
+    fn contacts_file(&self) -> PathBuf {
+        self.contacts_dir().join("contacts.json")
+    }
+
+    fn load(&self) -> Value {
+        serde_json::from_str(&fs::read_to_string(self.contacts_file()).unwrap()).unwrap()
+    }
+
+    fn write_config(&self, config: Value) {
+        let path = self.home.join("config").join("conn-rs").join("config.json");
+        fs::write(path, config.to_string()).unwrap();
+    }
+
+    fn run(&self, program: &str, args: &[&str]) -> Output {
+        Command::new(program)
+            .current_dir(&self.home)
+            .env("HOME", &self.home)
+            .env("XDG_DATA_HOME", self.home.join("data"))
+            .env("XDG_CONFIG_HOME", self.home.join("config"))
+            .env("XDG_CACHE_HOME", self.home.join("cache"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Run con-rs and return its standard output.
+    fn con_rs(&self, args: &[&str]) -> String {
+        let output = self.run(env!("CARGO_BIN_EXE_con-rs"), args);
+        assert!(
+            output.status.success(),
+            "con-rs {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run con-rs which is expected to fail and return its error output.
+    fn con_rs_error(&self, args: &[&str]) -> String {
+        let output = self.run(env!("CARGO_BIN_EXE_con-rs"), args);
+        assert!(!output.status.success(), "con-rs {:?} succeeded", args);
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    fn commit(&self, message: &str) {
+        let dir = self.contacts_dir();
+        let args = ["-C", dir.to_str().unwrap(), "commit", "-am", message];
+        let output = self.run("git", &args);
+        assert!(
+            output.status.success(),
+            "git commit failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn history_and_revert() {
+    let root = std::env::temp_dir().join(format!("con-rs-history-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let home = Home::new(&root);
+
+    let initial = root.join("initial.json");
+    let contacts = json!({
+        "contacts": [{
+            "id": "a1",
+            "fullName": "John Doe",
+            "entityName": null,
+            "tels": {},
+            "emails": {"work": "jd@example.com"},
+            "labels": {},
+        }],
+    });
+    fs::write(&initial, contacts.to_string()).unwrap();
+    home.con_rs(&["init", "--git", "--from", initial.to_str().unwrap()]);
+
+    let mut contacts = home.load();
+    contacts["contacts"][0]["emails"]["work"] = json!("john@example.com");
+    fs::write(home.contacts_file(), contacts.to_string()).unwrap();
+    home.commit("Change e-mail");
+
+    let history = home.con_rs(&["search", "--name", "John", "history"]);
+    let changes: Vec<&str> = history
+        .lines()
+        .filter(|line| line.starts_with("  "))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            r#"  emails.work: "jd@example.com" -> "john@example.com""#,
+            "  created",
+        ]
+    );
+
+    home.con_rs(&["search", "--name", "John", "history", "--revert", "HEAD~1"]);
+    assert_eq!(
+        home.load()["contacts"][0]["emails"]["work"],
+        "jd@example.com"
+    );
+
+    home.write_config(json!({ "storage": "vdir" }));
+    let error = home.con_rs_error(&["search", "--name", "John", "history"]);
+    assert!(
+        error.contains("History is not supported for vdir storage."),
+        "{}",
+        error
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
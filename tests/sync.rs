@@ -0,0 +1,178 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A clone of the contacts repository with its own data directory.
+struct Clone {
+    home: PathBuf,
+}
+
+impl Clone {
+    fn new(root: &Path, name: &str) -> Self {
+        let home = root.join(name);
+        fs::create_dir_all(home.join("data")).unwrap();
+        Self { home }
+    }
+
+    fn contacts_dir(&self) -> PathBuf {
+        self.home.join("data").join("conn-rs")
+    }
+
+    fn contacts_file(&self) -> PathBuf {
+        self.contacts_dir().join("contacts.json")
+    }
+
+    fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command
+            .env("HOME", &self.home)
+            .env("XDG_DATA_HOME", self.home.join("data"))
+            .env("XDG_CONFIG_HOME", self.home.join("config"))
+            .env("XDG_CACHE_HOME", self.home.join("cache"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com");
+        command
+    }
+
+    fn con_rs(&self, args: &[&str]) {
+        let output = self
+            .command(env!("CARGO_BIN_EXE_con-rs"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "con-rs {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn git(&self, dir: &Path, args: &[&str]) {
+        let output = self
+            .command("git")
+            .current_dir(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn load(&self) -> Value {
+        serde_json::from_str(&fs::read_to_string(self.contacts_file()).unwrap()).unwrap()
+    }
+
+    /// Change contacts file with a function.
+    fn update<F: FnOnce(&mut Value)>(&self, update: F) {
+        let mut contacts = self.load();
+        update(&mut contacts);
+        fs::write(
+            self.contacts_file(),
+            serde_json::to_string_pretty(&contacts).unwrap(),
+        )
+        .unwrap();
+    }
+}
+
+/// Mutable reference to a contact with a given ID.
+fn find<'a>(contacts: &'a mut Value, id: &str) -> &'a mut Value {
+    contacts["contacts"]
+        .as_array_mut()
+        .unwrap()
+        .iter_mut()
+        .find(|contact| contact["id"] == id)
+        .unwrap()
+}
+
+fn contact(id: &str, full_name: &str) -> Value {
+    json!({
+        "id": id,
+        "fullName": full_name,
+        "entityName": null,
+        "tels": {},
+        "emails": {},
+        "labels": {},
+    })
+}
+
+#[test]
+fn sync_two_clones() {
+    let root = std::env::temp_dir().join(format!("con-rs-sync-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    let remote = root.join("remote.git");
+    let alice = Clone::new(&root, "alice");
+    let bob = Clone::new(&root, "bob");
+    alice.git(
+        &root,
+        &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+    );
+
+    let initial = root.join("initial.json");
+    let contacts = json!({"contacts": [contact("a1", "John Doe"), contact("b2", "Jane Roe")]});
+    fs::write(&initial, contacts.to_string()).unwrap();
+    alice.con_rs(&["init", "--git", "--from", initial.to_str().unwrap()]);
+    let alice_dir = alice.contacts_dir();
+    alice.git(
+        &alice_dir,
+        &["remote", "add", "origin", remote.to_str().unwrap()],
+    );
+    alice.git(
+        &alice_dir,
+        &["push", "--quiet", "--set-upstream", "origin", "HEAD"],
+    );
+
+    bob.git(
+        &root,
+        &[
+            "clone",
+            "--quiet",
+            remote.to_str().unwrap(),
+            bob.contacts_dir().to_str().unwrap(),
+        ],
+    );
+
+    // Both change the same contact, each a different field, and add a
+    // contact.
+    alice.update(|contacts| {
+        find(contacts, "a1")["emails"]["work"] = json!("jd@example.com");
+        let contacts = contacts["contacts"].as_array_mut().unwrap();
+        contacts.push(contact("c3", "Alice Smith"));
+    });
+    bob.update(|contacts| {
+        find(contacts, "a1")["labels"]["nick"] = json!("jd");
+        find(contacts, "b2")["tags"] = json!(["family"]);
+        let contacts = contacts["contacts"].as_array_mut().unwrap();
+        contacts.push(contact("d4", "Bob Brown"));
+    });
+
+    alice.con_rs(&["sync"]);
+    bob.con_rs(&["sync"]);
+    alice.con_rs(&["sync"]);
+
+    let mut merged = alice.load();
+    assert_eq!(merged, bob.load());
+    let mut ids: Vec<&str> = merged["contacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|contact| contact["id"].as_str().unwrap())
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, ["a1", "b2", "c3", "d4"]);
+    assert_eq!(find(&mut merged, "a1")["emails"]["work"], "jd@example.com");
+    assert_eq!(find(&mut merged, "a1")["labels"]["nick"], "jd");
+    assert_eq!(find(&mut merged, "b2")["tags"], json!(["family"]));
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,85 @@
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Names of vCard files in a directory.
+fn cards(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".vcf"))
+        .collect();
+    names.sort();
+    names
+}
+
+fn con_rs(home: &Path, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_con-rs"))
+        .env("HOME", home)
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "con-rs {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn write_contacts(path: &Path, id: &str, full_name: &str) {
+    let contacts = json!({
+        "contacts": [{
+            "id": id,
+            "fullName": full_name,
+            "entityName": null,
+            "tels": {},
+            "emails": {},
+            "labels": {},
+        }],
+    });
+    fs::write(path, contacts.to_string()).unwrap();
+}
+
+#[test]
+fn forced_init_keeps_vdir() {
+    let home = std::env::temp_dir().join(format!("con-rs-vdir-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    let vdir: PathBuf = home.join("vdirsyncer").join("contacts");
+    fs::create_dir_all(home.join("config").join("conn-rs")).unwrap();
+    fs::write(
+        home.join("config").join("conn-rs").join("config.json"),
+        json!({ "storage": "vdir", "vdir": vdir }).to_string(),
+    )
+    .unwrap();
+
+    let initial = home.join("initial.json");
+    write_contacts(&initial, "a1", "John Doe");
+    con_rs(&home, &["init", "--from", initial.to_str().unwrap()]);
+    assert_eq!(cards(&vdir), ["a1.vcf"]);
+
+    write_contacts(&initial, "b2", "Jane Doe");
+    con_rs(
+        &home,
+        &["init", "--force", "--from", initial.to_str().unwrap()],
+    );
+    assert_eq!(cards(&vdir), ["b2.vcf"]);
+
+    // The backup is made in the directory with contacts, not next to the
+    // shared vdir.
+    assert_eq!(fs::read_dir(vdir.parent().unwrap()).unwrap().count(), 1);
+    let contacts_dir = home.join("data").join("conn-rs");
+    let backups: Vec<PathBuf> = fs::read_dir(&contacts_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".bak"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(cards(&backups[0]), ["a1.vcf"]);
+
+    fs::remove_dir_all(&home).unwrap();
+}